@@ -1,11 +1,19 @@
 #version 420 core
 
-in vec3 color;
+in vec4 color;
+in vec2 texCoord;
 out vec4 FragColor;
 
+uniform sampler2D uTexture;
+uniform vec4 uTint = vec4(1.0);
+// Seconds since the app started.
+uniform float uTime = 0.0;
+
 uniform float xPosition;
 uniform float yPosition;
 
 void main() {
-    FragColor = vec4(color, 1.0f);
+    // Vertex colors pulse between 50% and 100% brightness, once every two seconds or so.
+    vec3 pulse = vec3(0.75 + 0.25 * sin(uTime * 3.0));
+    FragColor = texture(uTexture, texCoord) * vec4(color.rgb * pulse, color.a) * uTint;
 }
\ No newline at end of file
@@ -1,14 +1,19 @@
 #version 420 core
 
 layout(location = 0) in vec3 vPosition;
-layout(location = 1) in vec3 vColor;
+layout(location = 1) in vec4 vColor;
+layout(location = 2) in vec2 vTexCoord;
+layout(location = 3) in vec2 vInstanceOffset;
 
-out vec3 color;
+out vec4 color;
+out vec2 texCoord;
 
-uniform float xPosition;
-uniform float yPosition;
+#include "camera.glsl"
+
+uniform mat4 uModel;
 
 void main() {
     color = vColor;
-    gl_Position = vec4((vPosition.x + xPosition), (vPosition.y + yPosition), vPosition.z, 1.0);
+    texCoord = vTexCoord;
+    gl_Position = uProjection * uView * uModel * vec4(vPosition + vec3(vInstanceOffset, 0.0), 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 420 core
+
+in vec4 color;
+in vec2 quadCoord;
+out vec4 FragColor;
+
+// Round dots: corners of the quad outside the unit circle are dropped.
+void main() {
+    if (dot(quadCoord, quadCoord) > 1.0) {
+        discard;
+    }
+    FragColor = color;
+}
@@ -0,0 +1,15 @@
+#version 420 core
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec4 vColor;
+
+out vec4 pointColor;
+
+#include "camera.glsl"
+
+// Points stay in view space here, the geometry shader expands them there so the quads always
+// face the camera.
+void main() {
+    pointColor = vColor;
+    gl_Position = uView * vec4(vPosition, 1.0);
+}
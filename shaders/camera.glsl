@@ -0,0 +1,6 @@
+// View and projection matrices shared by every program in the demo, uploaded once per frame into
+// the uniform buffer at FRAME_UBO_BINDING (see shaders.rs).
+layout(std140) uniform Frame {
+    mat4 uProjection;
+    mat4 uView;
+};
@@ -0,0 +1,17 @@
+#version 420 core
+
+in vec4 color;
+in vec2 texCoord;
+out vec4 FragColor;
+
+uniform vec2 uResolution;
+
+// Draws a circle in the middle of the screen. Coordinates are scaled by the
+// shorter side so the circle stays round whatever the aspect ratio is.
+void main() {
+    vec2 uv = (gl_FragCoord.xy - 0.5 * uResolution) / min(uResolution.x, uResolution.y);
+    if (length(uv) > 0.25) {
+        discard;
+    }
+    FragColor = vec4(color.rgb * 0.5, 1.0);
+}
@@ -0,0 +1,138 @@
+#version 420 core
+
+in vec4 color;
+in vec2 texCoord;
+out vec4 FragColor;
+
+uniform sampler2D uScene;
+// Smallest contrast around a pixel, relative to its brightest neighbour, that counts as an edge.
+uniform float uEdgeThreshold = 0.125;
+// Texels walked along an edge in each direction to find its ends.
+uniform int uSearchSteps = 8;
+
+// Contrast below this is never an edge, it keeps dark areas from being blurred.
+const float EDGE_THRESHOLD_MIN = 0.0312;
+const float SUBPIXEL_QUALITY = 0.75;
+
+float luma(vec3 rgb) {
+    return dot(rgb, vec3(0.299, 0.587, 0.114));
+}
+
+float lumaAt(vec2 uv) {
+    return luma(texture(uScene, uv).rgb);
+}
+
+// Fast approximate antialiasing over the rendered scene: finds edges by their contrast in luma,
+// walks along each to estimate how far this pixel is from its end, and samples the scene that far
+// across the edge, where bilinear filtering blends the two sides.
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(uScene, 0));
+    vec3 center = texture(uScene, texCoord).rgb;
+
+    float lumaCenter = luma(center);
+    float lumaDown = lumaAt(texCoord + vec2(0.0, -texel.y));
+    float lumaUp = lumaAt(texCoord + vec2(0.0, texel.y));
+    float lumaLeft = lumaAt(texCoord + vec2(-texel.x, 0.0));
+    float lumaRight = lumaAt(texCoord + vec2(texel.x, 0.0));
+
+    float lumaMin = min(lumaCenter, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+    float lumaMax = max(lumaCenter, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+    float range = lumaMax - lumaMin;
+    if (range < max(EDGE_THRESHOLD_MIN, lumaMax * uEdgeThreshold)) {
+        FragColor = vec4(center, 1.0);
+        return;
+    }
+
+    float lumaDownLeft = lumaAt(texCoord - texel);
+    float lumaUpRight = lumaAt(texCoord + texel);
+    float lumaUpLeft = lumaAt(texCoord + vec2(-texel.x, texel.y));
+    float lumaDownRight = lumaAt(texCoord + vec2(texel.x, -texel.y));
+
+    float lumaDownUp = lumaDown + lumaUp;
+    float lumaLeftRight = lumaLeft + lumaRight;
+    float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+    float lumaDownCorners = lumaDownLeft + lumaDownRight;
+    float lumaRightCorners = lumaDownRight + lumaUpRight;
+    float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+    // The edge runs along whichever axis the contrast changes least along.
+    float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners)
+        + abs(-2.0 * lumaCenter + lumaDownUp) * 2.0
+        + abs(-2.0 * lumaRight + lumaRightCorners);
+    float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners)
+        + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0
+        + abs(-2.0 * lumaDown + lumaDownCorners);
+    bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+    // The edge lies on the side of the steeper gradient.
+    float luma1 = isHorizontal ? lumaDown : lumaLeft;
+    float luma2 = isHorizontal ? lumaUp : lumaRight;
+    float gradient1 = luma1 - lumaCenter;
+    float gradient2 = luma2 - lumaCenter;
+    bool is1Steepest = abs(gradient1) >= abs(gradient2);
+    float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    float stepLength = isHorizontal ? texel.y : texel.x;
+    float lumaLocalAverage;
+    if (is1Steepest) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+    } else {
+        lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+    }
+
+    // Halfway between this pixel and its neighbour across the edge.
+    vec2 edgeUv = texCoord;
+    if (isHorizontal) {
+        edgeUv.y += 0.5 * stepLength;
+    } else {
+        edgeUv.x += 0.5 * stepLength;
+    }
+
+    // Walk both ways along the edge until the luma no longer matches it.
+    vec2 offset = isHorizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+    vec2 uv1 = edgeUv - offset;
+    vec2 uv2 = edgeUv + offset;
+    float lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+    float lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+    bool reached1 = abs(lumaEnd1) >= gradientScaled;
+    bool reached2 = abs(lumaEnd2) >= gradientScaled;
+    for (int i = 1; i < uSearchSteps && !(reached1 && reached2); i++) {
+        if (!reached1) {
+            uv1 -= offset;
+            lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+            reached1 = abs(lumaEnd1) >= gradientScaled;
+        }
+        if (!reached2) {
+            uv2 += offset;
+            lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+            reached2 = abs(lumaEnd2) >= gradientScaled;
+        }
+    }
+
+    float distance1 = isHorizontal ? texCoord.x - uv1.x : texCoord.y - uv1.y;
+    float distance2 = isHorizontal ? uv2.x - texCoord.x : uv2.y - texCoord.y;
+    bool isDirection1 = distance1 < distance2;
+    float edgeLength = distance1 + distance2;
+    float pixelOffset = 0.5 - min(distance1, distance2) / edgeLength;
+
+    // Only blend towards the nearer end if its luma varies the same way as this pixel's.
+    bool isLumaCenterSmaller = lumaCenter < lumaLocalAverage;
+    bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCenterSmaller;
+    float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+    // Aliasing smaller than a pixel, like thin lines, shows as contrast with the 3x3 average.
+    float lumaAverage = (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners + lumaRightCorners)
+        / 12.0;
+    float subPixel = clamp(abs(lumaAverage - lumaCenter) / range, 0.0, 1.0);
+    subPixel = (-2.0 * subPixel + 3.0) * subPixel * subPixel;
+    finalOffset = max(finalOffset, subPixel * subPixel * SUBPIXEL_QUALITY);
+
+    vec2 finalUv = texCoord;
+    if (isHorizontal) {
+        finalUv.y += finalOffset * stepLength;
+    } else {
+        finalUv.x += finalOffset * stepLength;
+    }
+    FragColor = vec4(texture(uScene, finalUv).rgb, 1.0);
+}
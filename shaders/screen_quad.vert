@@ -0,0 +1,18 @@
+#version 420 core
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec4 vColor;
+layout(location = 2) in vec2 vTexCoord;
+
+out vec4 color;
+out vec2 texCoord;
+
+uniform mat4 uModel;
+
+// Positions are in clip space, placed by uModel alone, for geometry drawn over or under the
+// whole screen regardless of the camera.
+void main() {
+    color = vColor;
+    texCoord = vTexCoord;
+    gl_Position = uModel * vec4(vPosition, 1.0);
+}
@@ -0,0 +1,8 @@
+#version 420 core
+
+in vec3 color;
+out vec4 FragColor;
+
+void main() {
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,8 @@
+#version 420 core
+
+layout(location = 0) in vec3 vPosition;
+
+// Control points go through untouched, the evaluation shader does the transforms.
+void main() {
+    gl_Position = vec4(vPosition, 1.0);
+}
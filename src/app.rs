@@ -0,0 +1,433 @@
+use std::{path::Path, sync::mpsc::Receiver};
+
+use glfw::{Action, Context, Key, WindowEvent};
+
+use crate::color::Color;
+use crate::debug;
+use crate::error::Error;
+use crate::frame_latency::FrameLatencyLimiter;
+use crate::frame_stats::FrameStats;
+use crate::input::Input;
+use crate::profiling;
+use crate::render_state::{BlendFactor, RenderState};
+use crate::shaders::{ShaderError, ShaderProgram};
+
+// Switches between windowed mode and fullscreen on the primary monitor.
+const FULLSCREEN_KEY: Key = Key::F11;
+
+const CLEAR_COLOR: Color = Color::rgb(0.2, 0.3, 0.3);
+
+// Appends the average frame rate, frame time and CPU scope timings to the window title, updated
+// once per second.
+const SHOW_FRAME_STATS: bool = true;
+
+// Window and context settings for `App::new`.
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    // Requested GL version (major, minor), always with a core profile.
+    pub gl_version: (u32, u32),
+    pub resizable: bool,
+    pub vsync: bool,
+    // Samples per pixel for multisampling the default framebuffer, 0 for none. Offscreen
+    // framebuffers pick their own, see `Framebuffer`.
+    pub samples: u32,
+    // How many frames the CPU may queue ahead of the GPU, 0 to leave it to the driver. See
+    // `FrameLatencyLimiter` for what is supported where.
+    pub frame_latency: usize,
+    // Some drivers show whatever was in video memory until the first frame is presented, which
+    // flashes garbage while shaders and buffers are being set up. When set, the window is cleared
+    // to the clear color and presented once right after the context is created.
+    pub clear_before_first_frame: bool,
+    // Requests a depth buffer and turns on depth testing, which 3D scenes with overlapping
+    // geometry need. Depth testing can still be toggled later through `App::render_state`, but
+    // without a depth buffer it has no effect.
+    pub depth_test: bool,
+    // Initial value of `App::time_uniform`.
+    pub time_uniform: bool,
+    // Rebuilds the shader program from the paths it was created from, so they have to still be
+    // on disk (relative to the working directory) when the key is pressed. A failed reload keeps
+    // the current program. None disables the key.
+    pub reload_shaders_key: Option<Key>,
+    // When set, the shader sources are checked every frame and the program is rebuilt as soon as
+    // one of them is saved.
+    pub hot_reload_shaders: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            title: String::from("OpenGL in Rust"),
+            gl_version: (4, 2),
+            resizable: true,
+            vsync: true,
+            samples: 0,
+            frame_latency: 1,
+            clear_before_first_frame: true,
+            depth_test: true,
+            time_uniform: true,
+            reload_shaders_key: Some(Key::R),
+            hot_reload_shaders: true,
+        }
+    }
+}
+
+// What an `App` runs: `on_render` is called once per frame between the clear and the swap, and
+// `on_event` for every window event after the app has handled it.
+pub trait Scene {
+    fn on_render(&mut self, app: &mut App, dt: f32);
+
+    fn on_event(&mut self, _app: &mut App, _event: &WindowEvent) {}
+}
+
+// Owns the window, its GL context and the shader program, and drives the frame loop. The app
+// handles the events every scene needs: Escape, wireframe toggling (1/2), shader reloading,
+// fullscreen toggling and framebuffer resizes.
+pub struct App {
+    pub shader_program: ShaderProgram,
+    pub render_state: RenderState,
+    pub content_scale: (f32, f32),
+    pub input: Input,
+    pub frame_stats: FrameStats,
+    // Uploads the time since GLFW was initialized, in seconds, to `uniform float uTime` before
+    // every frame, for shaders that animate by themselves.
+    pub time_uniform: bool,
+    frame_latency: FrameLatencyLimiter,
+    reload_shaders_key: Option<Key>,
+    hot_reload_shaders: bool,
+    // The title from the config, without frame stats.
+    title: String,
+    // Window position and size (x, y, width, height) from before going fullscreen, None while
+    // windowed.
+    windowed_geometry: Option<(i32, i32, i32, i32)>,
+    // `glfw.get_time()` at the start of the previous frame, in seconds.
+    last_frame: f64,
+    // Fields drop in declaration order, and destroying the window destroys the context, so these
+    // come last: everything above may delete GL objects when dropped.
+    pub window: glfw::Window,
+    pub events: Receiver<(f64, WindowEvent)>,
+    pub glfw: glfw::Glfw,
+}
+
+impl App {
+    pub fn new(
+        config: WindowConfig,
+        vertex_shader_path: impl AsRef<Path>,
+        fragment_shader_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS)?;
+
+        let (major, minor) = config.gl_version;
+        glfw.window_hint(glfw::WindowHint::ContextVersion(major, minor));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(
+            glfw::OpenGlProfileHint::Core,
+        ));
+        glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(cfg!(debug_assertions)));
+        glfw.window_hint(glfw::WindowHint::DepthBits(config.depth_test.then_some(24)));
+        glfw.window_hint(glfw::WindowHint::Samples(
+            (config.samples > 0).then_some(config.samples),
+        ));
+
+        let (mut window, events) = glfw
+            .create_window(
+                config.width,
+                config.height,
+                &config.title,
+                glfw::WindowMode::Windowed,
+            )
+            .ok_or(Error::WindowCreation)?;
+
+        window.set_key_polling(true);
+        window.set_cursor_pos_polling(true);
+        window.set_mouse_button_polling(true);
+        window.set_scroll_polling(true);
+        window.set_framebuffer_size_polling(true);
+        window.set_content_scale_polling(true);
+        window.set_resizable(config.resizable);
+        window.make_current();
+
+        gl::load_with(|s| window.get_proc_address(s));
+
+        if cfg!(debug_assertions) && !unsafe { debug::enable_debug_output() } {
+            println!("GL_KHR_debug is not available, GL errors are only reported by gl_check!");
+        }
+
+        // Window sizes and cursor positions are in logical screen coordinates, while the
+        // framebuffer is in physical pixels. On HiDPI displays the two differ by the content
+        // scale, so the viewport has to come from the framebuffer size. Anything sized in logical
+        // units (UI, text) should be multiplied by the content scale.
+        let (framebuffer_width, framebuffer_height) = window.get_framebuffer_size();
+        let content_scale = window.get_content_scale();
+        if cfg!(debug_assertions) {
+            println!("Content scale: {:?}", content_scale);
+        }
+        unsafe { gl::Viewport(0, 0, framebuffer_width, framebuffer_height) };
+
+        // On by default in most drivers, but not all.
+        if config.samples > 0 {
+            unsafe { gl::Enable(gl::MULTISAMPLE) };
+        }
+
+        let mut render_state = RenderState::new();
+        unsafe {
+            render_state.set_depth_test(config.depth_test);
+            // Blending stays off until a scene turns it on, but uses regular alpha blending when
+            // it does.
+            render_state.set_blend_func(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+            render_state.set_clear_color(CLEAR_COLOR);
+        }
+
+        if config.clear_before_first_frame {
+            unsafe { render_state.clear() };
+            window.swap_buffers();
+        }
+
+        let shader_program = unsafe {
+            cpu_scope!("shader compilation");
+            ShaderProgram::from_files(vertex_shader_path, fragment_shader_path)?
+        };
+
+        if cfg!(debug_assertions) {
+            let (format, length) = unsafe { shader_program.binary_info() };
+            println!("Program binary: format {:#x}, {} bytes", format, length);
+        }
+
+        unsafe {
+            set_resolution_uniform(
+                &mut render_state,
+                &shader_program,
+                framebuffer_width,
+                framebuffer_height,
+            )
+        };
+
+        let mut app = Self {
+            glfw,
+            window,
+            events,
+            shader_program,
+            render_state,
+            content_scale,
+            input: Input::new(),
+            frame_stats: FrameStats::new(),
+            time_uniform: config.time_uniform,
+            frame_latency: FrameLatencyLimiter::new(config.frame_latency),
+            reload_shaders_key: config.reload_shaders_key,
+            hot_reload_shaders: config.hot_reload_shaders,
+            title: config.title,
+            windowed_geometry: None,
+            last_frame: 0.0,
+        };
+        app.set_vsync(config.vsync);
+
+        Ok(app)
+    }
+}
+
+impl App {
+    // `dt` is the time since the previous frame started, in seconds, so scenes can express motion
+    // in units per second regardless of frame rate.
+    pub fn run(&mut self, scene: &mut impl Scene) {
+        self.last_frame = self.glfw.get_time();
+
+        while !self.window.should_close() {
+            self.glfw.poll_events();
+
+            if self.hot_reload_shaders && self.shader_program.poll_source_changes() {
+                unsafe { self.reload_shaders() };
+            }
+
+            let now = self.glfw.get_time();
+            let dt = (now - self.last_frame) as f32;
+            self.last_frame = now;
+
+            // The scopes timed during the previous frame, or during setup for the first one.
+            self.frame_stats.record_cpu(&profiling::end_frame());
+            if self.frame_stats.record(dt) && SHOW_FRAME_STATS {
+                let mut title = format!(
+                    "{} - {:.0} FPS ({:.2} ms)",
+                    self.title,
+                    self.frame_stats.avg_fps(),
+                    self.frame_stats.avg_frame_ms()
+                );
+                for (name, ms) in self.frame_stats.avg_cpu_ms() {
+                    title.push_str(&format!(" | {} {:.2} ms", name, ms));
+                }
+                self.window.set_title(&title);
+            }
+
+            unsafe {
+                self.render_state.clear();
+                if self.time_uniform {
+                    set_time_uniform(&mut self.render_state, &self.shader_program, now);
+                }
+            }
+
+            {
+                cpu_scope!("render");
+                scene.on_render(self, dt);
+            }
+            self.input.end_frame();
+
+            self.window.swap_buffers();
+            unsafe { self.frame_latency.end_frame() };
+
+            // Collected up front, the scene gets the whole app while handling each event.
+            let events: Vec<WindowEvent> = glfw::flush_messages(&self.events)
+                .map(|(_, event)| event)
+                .collect();
+
+            for event in events {
+                self.input.handle_event(&event);
+                self.handle_event(&event);
+                scene.on_event(self, &event);
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) {
+        let reload_key = self.reload_shaders_key;
+        match *event {
+            WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+                self.window.set_should_close(true)
+            }
+            WindowEvent::Key(Key::Num1, _, Action::Press, _) => unsafe {
+                println!("Wireframe OFF");
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            },
+            WindowEvent::Key(Key::Num2, _, Action::Press, _) => unsafe {
+                println!("Wireframe ON");
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+            },
+            WindowEvent::Key(key, _, Action::Press, _) if Some(key) == reload_key => unsafe {
+                self.reload_shaders()
+            },
+            WindowEvent::Key(key, _, Action::Press, _) if key == FULLSCREEN_KEY => {
+                self.toggle_fullscreen()
+            }
+            WindowEvent::FramebufferSize(width, height) => unsafe {
+                gl::Viewport(0, 0, width, height);
+                set_resolution_uniform(&mut self.render_state, &self.shader_program, width, height);
+            },
+            WindowEvent::ContentScale(x_scale, y_scale) => {
+                self.content_scale = (x_scale, y_scale);
+                if cfg!(debug_assertions) {
+                    println!("Content scale changed to {:?}", self.content_scale);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    unsafe fn reload_shaders(&mut self) {
+        match self.shader_program.reload() {
+            Ok(program) => {
+                self.shader_program = program;
+                self.render_state.invalidate_bindings();
+
+                let (width, height) = self.window.get_framebuffer_size();
+                set_resolution_uniform(&mut self.render_state, &self.shader_program, width, height);
+                println!("Shaders reloaded");
+            }
+            Err(err) => eprintln!("Failed to reload shaders: {}", err),
+        }
+    }
+}
+
+impl App {
+    // Waits for one vertical blank per swap when enabled, otherwise frames are presented as soon
+    // as they're done. Drivers may be configured to override either.
+    pub fn set_vsync(&mut self, enabled: bool) {
+        // The swap interval applies to the current context, which is this window's.
+        self.glfw.set_swap_interval(if enabled {
+            glfw::SwapInterval::Sync(1)
+        } else {
+            glfw::SwapInterval::None
+        });
+    }
+}
+
+impl App {
+    // Lower latencies make input feel more immediate at the cost of throughput, since the CPU
+    // waits for the GPU more often. Falls back to 0 with a warning where fences aren't available.
+    pub fn set_frame_latency(&mut self, max_frames: usize) {
+        self.frame_latency.set_frame_latency(max_frames);
+    }
+
+    pub fn frame_latency(&self) -> usize {
+        self.frame_latency.frame_latency()
+    }
+}
+
+impl App {
+    // Going back to windowed mode restores the position and size the window had before.
+    pub fn toggle_fullscreen(&mut self) {
+        match self.windowed_geometry.take() {
+            Some((x, y, width, height)) => self.window.set_monitor(
+                glfw::WindowMode::Windowed,
+                x,
+                y,
+                width as u32,
+                height as u32,
+                None,
+            ),
+            None => {
+                let (x, y) = self.window.get_pos();
+                let (width, height) = self.window.get_size();
+
+                let window = &mut self.window;
+                let switched = self.glfw.with_primary_monitor(|_, monitor| {
+                    let monitor = monitor?;
+                    let mode = monitor.get_video_mode()?;
+                    window.set_monitor(
+                        glfw::WindowMode::FullScreen(monitor),
+                        0,
+                        0,
+                        mode.width,
+                        mode.height,
+                        Some(mode.refresh_rate),
+                    );
+                    Some(())
+                });
+
+                match switched {
+                    Some(()) => self.windowed_geometry = Some((x, y, width, height)),
+                    None => eprintln!("No primary monitor to go fullscreen on"),
+                }
+            }
+        }
+
+        let (width, height) = self.window.get_framebuffer_size();
+        unsafe {
+            gl::Viewport(0, 0, width, height);
+            set_resolution_uniform(&mut self.render_state, &self.shader_program, width, height);
+        }
+    }
+}
+
+// Uploads `time` to `uniform float uTime`, if the program uses it. Leaves the program bound.
+unsafe fn set_time_uniform(render_state: &mut RenderState, program: &ShaderProgram, time: f64) {
+    render_state.use_program(program.id);
+    match program.set_uniform_f32("uTime", time as f32) {
+        Ok(()) | Err(ShaderError::UniformNotFound(_)) => {}
+        Err(err) => eprintln!("Failed to set uTime: {}", err),
+    }
+}
+
+// Uploads the framebuffer size in pixels to `uniform vec2 uResolution`, if the program uses it.
+// Leaves the program bound.
+unsafe fn set_resolution_uniform(
+    render_state: &mut RenderState,
+    program: &ShaderProgram,
+    width: i32,
+    height: i32,
+) {
+    render_state.use_program(program.id);
+    program
+        .set_uniform_vec2("uResolution", [width as f32, height as f32])
+        .ok();
+}
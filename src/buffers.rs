@@ -1,64 +1,505 @@
-use std::{mem::size_of_val, os::raw::c_void};
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    mem::{size_of, size_of_val},
+    ops::{Deref, DerefMut},
+    os::raw::c_void,
+    slice,
+};
 
 use gl::types::*;
+use thiserror::Error;
 
-pub struct Buffer {
+use crate::debug;
+use crate::render_state::{max_vertex_attribs, RenderState};
+
+#[derive(Debug, Error)]
+pub enum BufferError {
+    #[error("No OpenGL context is current on this thread, call window.make_current() first")]
+    NoContext,
+    // Carries the buffer target, or gl::VERTEX_ARRAY for vertex arrays.
+    #[error("Failed to create GL object of type {0:#x}")]
+    CreationFailed(GLenum),
+    #[error(
+        "Attribute location {location} is out of range, this context supports locations 0..{max}"
+    )]
+    AttributeOutOfRange { location: u32, max: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    StaticDraw,
+    DynamicDraw,
+    StreamDraw,
+}
+
+impl BufferUsage {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            BufferUsage::StaticDraw => gl::STATIC_DRAW,
+            BufferUsage::DynamicDraw => gl::DYNAMIC_DRAW,
+            BufferUsage::StreamDraw => gl::STREAM_DRAW,
+        }
+    }
+}
+
+// GL objects belong to the context they were created in, and a context is current on one thread
+// at a time. The wrappers below are neither Send nor Sync, so they can only be created, used and
+// dropped on the thread that owns the context.
+//
+// `T` is the element type the buffer holds, e.g. a vertex struct or u32 for indices.
+pub struct Buffer<T> {
     id: u32,
     buffer_type: GLenum,
+    size: Cell<isize>,
+    len: Cell<usize>,
+    usage: Cell<GLenum>,
+    _element: PhantomData<T>,
+    _not_send: PhantomData<*const ()>,
 }
 
-impl Buffer {
-    pub unsafe fn new(buffer_type: GLenum) -> Self {
-        let mut buffer = Self { id: 0, buffer_type };
+impl<T> Buffer<T> {
+    // Fails instead of asserting without a context, since creating the first buffers is where
+    // forgetting `make_current` usually shows up.
+    pub unsafe fn new(buffer_type: GLenum) -> Result<Self, BufferError> {
+        if !debug::context_is_current() {
+            return Err(BufferError::NoContext);
+        }
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        gl_trace!("glGenBuffers -> buffer {}", id);
+
+        if id == 0 {
+            return Err(BufferError::CreationFailed(buffer_type));
+        }
+
+        Ok(Self {
+            id,
+            buffer_type,
+            size: Cell::new(0),
+            len: Cell::new(0),
+            usage: Cell::new(gl::STATIC_DRAW),
+            _element: PhantomData,
+            _not_send: PhantomData,
+        })
+    }
+
+    // Wraps a buffer created elsewhere. The wrapper owns it from here on and deletes it on drop
+    // unless it is handed back with `into_raw`.
+    pub unsafe fn from_raw(id: u32, buffer_type: GLenum) -> Self {
+        debug::assert_context_current();
+        let buffer = Self {
+            id,
+            buffer_type,
+            size: Cell::new(0),
+            len: Cell::new(0),
+            usage: Cell::new(gl::STATIC_DRAW),
+            _element: PhantomData,
+            _not_send: PhantomData,
+        };
 
-        gl::GenBuffers(1, &mut buffer.id);
+        buffer.size.set(buffer.gl_size());
+        buffer.len.set(Self::len_for_size(buffer.size.get()));
+        buffer.usage.set(buffer.gl_usage());
 
-        return buffer;
+        buffer
+    }
+
+    // Releases ownership of the GL buffer without deleting it.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        std::mem::forget(self);
+
+        id
     }
 }
 
-impl Buffer {
+impl<T> Buffer<T> {
     pub unsafe fn bind(&self) {
+        debug::assert_context_current();
+        gl_trace!("glBindBuffer({:#x}, {})", self.buffer_type, self.id);
         gl::BindBuffer(self.buffer_type, self.id);
     }
+
+    // Attaches the whole buffer to an indexed binding point of its target, which is what
+    // connects it to a `layout(binding = N)` uniform or storage block in GLSL.
+    pub unsafe fn bind_base(&self, binding_point: u32) {
+        debug::assert_context_current();
+        gl_trace!(
+            "glBindBufferBase({:#x}, {}, {})",
+            self.buffer_type,
+            binding_point,
+            self.id
+        );
+        gl::BindBufferBase(self.buffer_type, binding_point, self.id);
+    }
+}
+
+// Uniform buffers back `layout(std140) uniform` blocks, see `ShaderProgram::bind_uniform_block`
+// and `Std140Builder` for the layout.
+impl<T> Buffer<T> {
+    pub unsafe fn new_uniform() -> Result<Self, BufferError> {
+        Self::new(gl::UNIFORM_BUFFER)
+    }
+}
+
+// Shader storage buffers back `layout(std430, binding = N) buffer` blocks, which shaders (compute
+// shaders in particular) can write as well as read. They need GL 4.3, so on the 4.2 context they
+// are only there if the driver exposes ARB_shader_storage_buffer_object.
+impl<T> Buffer<T> {
+    pub unsafe fn new_shader_storage() -> Result<Self, BufferError> {
+        Self::new(gl::SHADER_STORAGE_BUFFER)
+    }
+}
+
+impl<T: Copy> Buffer<T> {
+    // Copies the whole store back to the CPU, e.g. the results of a compute shader. Writes made
+    // by shaders have to be made visible first with
+    // `gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT)`. Returns None if the store couldn't be
+    // mapped.
+    pub unsafe fn read_back(&self) -> Option<Vec<T>> {
+        debug::assert_context_current();
+        let len = self.len.get();
+        let length = (len * size_of::<T>()) as isize;
+        let mut data: Vec<T> = Vec::with_capacity(len);
+
+        if length == 0 {
+            return Some(data);
+        }
+
+        self.bind();
+        gl_trace!(
+            "glMapBufferRange({:#x}, offset 0, {} bytes, GL_MAP_READ_BIT) on buffer {}",
+            self.buffer_type,
+            length,
+            self.id
+        );
+        let ptr = gl::MapBufferRange(self.buffer_type, 0, length, gl::MAP_READ_BIT);
+        if ptr.is_null() {
+            return None;
+        }
+
+        std::ptr::copy_nonoverlapping(ptr as *const T, data.as_mut_ptr(), len);
+        data.set_len(len);
+        gl::UnmapBuffer(self.buffer_type);
+
+        Some(data)
+    }
 }
 
-impl Buffer {
-    pub unsafe fn set_data<D>(&self, data: &[D], usage: GLuint) {
+impl<T> Buffer<T> {
+    pub unsafe fn set_data(&self, data: &[T], usage: BufferUsage) {
+        debug::assert_context_current();
         self.bind();
-        let (_, data_bytes, _) = data.align_to::<f32>();
+        let usage = usage.to_gl();
+        let size = size_of_val(data) as isize;
+        gl_trace!(
+            "glBufferData({:#x}, {} bytes, usage {:#x}) on buffer {}",
+            self.buffer_type,
+            size,
+            usage,
+            self.id
+        );
 
         gl::BufferData(
             self.buffer_type,
-            size_of_val(data_bytes) as isize,
+            size,
             data.as_ptr() as *const c_void,
             usage,
         );
+
+        self.size.set(size);
+        self.len.set(data.len());
+        self.usage.set(usage);
+    }
+
+    // Size in bytes of the store allocated by the last `set_data` or `resize`.
+    pub fn size(&self) -> isize {
+        self.size.get()
+    }
+
+    // Number of `T`s the store holds, e.g. the count to pass to glDrawElements for an index
+    // buffer.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    fn len_for_size(size: isize) -> usize {
+        size.max(0) as usize / size_of::<T>().max(1)
+    }
+}
+
+impl<T> Buffer<T> {
+    // Overwrites part of the store in place without reallocating it, for geometry that changes
+    // every frame. `offset` is in bytes and the data has to fit in the store allocated by the
+    // last `set_data` or `resize`.
+    pub unsafe fn update_sub_data(&self, offset: isize, data: &[T]) {
+        debug::assert_context_current();
+        let length = size_of_val(data) as isize;
+        debug_assert!(
+            offset >= 0 && offset + length <= self.size.get(),
+            "updating {} bytes at offset {} overruns buffer {} of {} bytes",
+            length,
+            offset,
+            self.id,
+            self.size.get()
+        );
+
+        self.bind();
+        gl_trace!(
+            "glBufferSubData({:#x}, offset {}, {} bytes) on buffer {}",
+            self.buffer_type,
+            offset,
+            length,
+            self.id
+        );
+        gl::BufferSubData(
+            self.buffer_type,
+            offset,
+            length,
+            data.as_ptr() as *const c_void,
+        );
+    }
+}
+
+impl<T> Buffer<T> {
+    // Unlike `size`, these ask GL for the store it actually allocated.
+    pub unsafe fn gl_size(&self) -> isize {
+        debug::assert_context_current();
+        self.get_parameter(gl::BUFFER_SIZE) as isize
+    }
+
+    pub unsafe fn gl_usage(&self) -> GLenum {
+        debug::assert_context_current();
+        self.get_parameter(gl::BUFFER_USAGE) as GLenum
+    }
+
+    unsafe fn get_parameter(&self, pname: GLenum) -> GLint {
+        self.bind();
+        let mut value: GLint = 0;
+        gl::GetBufferParameteriv(self.buffer_type, pname, &mut value);
+
+        value
+    }
+}
+
+impl<T> Buffer<T> {
+    // Reallocates the store to `new_size` bytes, keeping the first min(old, new) bytes. The data
+    // goes through a scratch buffer so the buffer id stays the same and VAOs that reference it
+    // remain valid. Everything happens on the copy targets, so the buffer's own target (and for
+    // element buffers, the bound VAO) is left alone.
+    pub unsafe fn resize(&self, new_size: isize) {
+        debug::assert_context_current();
+        let preserved = self.size.get().min(new_size);
+        gl_trace!(
+            "resize buffer {} from {} to {} bytes, preserving {}",
+            self.id,
+            self.size.get(),
+            new_size,
+            preserved
+        );
+        let mut scratch: u32 = 0;
+
+        if preserved > 0 {
+            gl::GenBuffers(1, &mut scratch);
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, scratch);
+            gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                preserved,
+                std::ptr::null(),
+                gl::STREAM_COPY,
+            );
+            gl::BindBuffer(gl::COPY_READ_BUFFER, self.id);
+            gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, preserved);
+        }
+
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.id);
+        gl::BufferData(
+            gl::COPY_WRITE_BUFFER,
+            new_size,
+            std::ptr::null(),
+            self.usage.get(),
+        );
+
+        if preserved > 0 {
+            gl::BindBuffer(gl::COPY_READ_BUFFER, scratch);
+            gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, preserved);
+            gl::DeleteBuffers(1, &scratch);
+        }
+
+        self.size.set(new_size);
+        self.len.set(Self::len_for_size(new_size));
+    }
+}
+
+impl<T> Buffer<T> {
+    // Maps `length` bytes starting at `offset` for writing. Pass gl::MAP_FLUSH_EXPLICIT_BIT in
+    // `flags` to only publish the sub-ranges handed to `BufferMapping::flush`.
+    pub unsafe fn map_range_mut(
+        &self,
+        offset: isize,
+        length: isize,
+        flags: GLbitfield,
+    ) -> Option<BufferMapping<'_, T>> {
+        debug::assert_context_current();
+        self.bind();
+        gl_trace!(
+            "glMapBufferRange({:#x}, offset {}, {} bytes, flags {:#x}) on buffer {}",
+            self.buffer_type,
+            offset,
+            length,
+            flags | gl::MAP_WRITE_BIT,
+            self.id
+        );
+        let ptr = gl::MapBufferRange(self.buffer_type, offset, length, flags | gl::MAP_WRITE_BIT);
+
+        if ptr.is_null() {
+            return None;
+        }
+
+        Some(BufferMapping {
+            buffer: self,
+            data: slice::from_raw_parts_mut(ptr as *mut u8, length as usize),
+        })
+    }
+}
+
+pub struct BufferMapping<'a, T> {
+    buffer: &'a Buffer<T>,
+    data: &'a mut [u8],
+}
+
+impl<T> BufferMapping<'_, T> {
+    // `sub_offset` is relative to the start of the mapped range, not the buffer.
+    pub unsafe fn flush(&self, sub_offset: isize, sub_length: isize) {
+        debug::assert_context_current();
+        debug_assert!(sub_offset >= 0 && (sub_offset + sub_length) as usize <= self.data.len());
+
+        self.buffer.bind();
+        gl_trace!(
+            "glFlushMappedBufferRange(offset {}, {} bytes) on buffer {}",
+            sub_offset,
+            sub_length,
+            self.buffer.id
+        );
+        gl::FlushMappedBufferRange(self.buffer.buffer_type, sub_offset, sub_length);
+    }
+}
+
+impl<T> Deref for BufferMapping<'_, T> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl<T> DerefMut for BufferMapping<'_, T> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.data
     }
 }
 
-impl Drop for Buffer {
+impl<T> Drop for BufferMapping<'_, T> {
     fn drop(&mut self) {
+        unsafe {
+            self.buffer.bind();
+            gl_trace!("glUnmapBuffer on buffer {}", self.buffer.id);
+            gl::UnmapBuffer(self.buffer.buffer_type);
+        }
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        gl_trace!("glDeleteBuffers({})", self.id);
         unsafe { gl::DeleteBuffers(1, [self.id].as_mut_ptr()) }
     }
 }
 
 pub struct VertexArray {
     pub id: u32,
+    // Attributes queued by `add_attribute`, applied by `finish`.
+    pending_attributes: Vec<VertexAttribute>,
+    _not_send: PhantomData<*const ()>,
+}
+
+struct VertexAttribute {
+    location: u32,
+    count: i32,
+    data_type: GLenum,
+}
+
+// Size in bytes of one component of the given vertex attribute type.
+fn attribute_type_size(data_type: GLenum) -> usize {
+    match data_type {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+        gl::INT | gl::UNSIGNED_INT | gl::FLOAT | gl::FIXED => 4,
+        gl::DOUBLE => 8,
+        _ => panic!("unsupported vertex attribute type {:#x}", data_type),
+    }
 }
 
 impl VertexArray {
-    pub unsafe fn new() -> Self {
-        let mut vao = Self { id: 0 };
-        gl::GenVertexArrays(1, &mut vao.id);
+    // Like `Buffer::new`, fails without a context.
+    pub unsafe fn new() -> Result<Self, BufferError> {
+        if !debug::context_is_current() {
+            return Err(BufferError::NoContext);
+        }
+        let mut id = 0;
+        gl::GenVertexArrays(1, &mut id);
+        gl_trace!("glGenVertexArrays -> vertex array {}", id);
 
-        return vao;
+        if id == 0 {
+            return Err(BufferError::CreationFailed(gl::VERTEX_ARRAY));
+        }
+
+        Ok(Self {
+            id,
+            pending_attributes: Vec::new(),
+            _not_send: PhantomData,
+        })
     }
 
-    pub unsafe fn bind(&self) {
+    // Wraps a vertex array created elsewhere, which is then deleted on drop unless handed back
+    // with `into_raw`.
+    pub unsafe fn from_raw(id: u32) -> Self {
+        debug::assert_context_current();
+        Self {
+            id,
+            pending_attributes: Vec::new(),
+            _not_send: PhantomData,
+        }
+    }
+
+    // Releases ownership of the GL vertex array without deleting it.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        std::mem::forget(self);
+
+        id
+    }
+
+    // Skips the bind when the render state already has this vertex array bound.
+    pub unsafe fn bind(&self, render_state: &mut RenderState) {
+        render_state.bind_vertex_array(self.id);
+    }
+
+    // For the setup methods below, which have no RenderState to go through. Binding this way
+    // leaves the render state's cache stale, see `RenderState::invalidate_bindings`.
+    pub(crate) unsafe fn bind_uncached(&self) {
+        debug::assert_context_current();
+        gl_trace!("glBindVertexArray({})", self.id);
         gl::BindVertexArray(self.id);
     }
 
+    // Sources the attribute from the buffer currently bound to GL_ARRAY_BUFFER. That binding isn't
+    // part of the vertex array state, so binding this vertex array first leaves it alone.
     pub unsafe fn set_layout(
         &self,
         location: u32,
@@ -66,23 +507,476 @@ impl VertexArray {
         data_type: GLenum,
         normalized: GLboolean,
         stride: i32,
-    ) {
+    ) -> Result<(), BufferError> {
+        debug::assert_context_current();
+        check_attribute_location(location)?;
+        self.bind_uncached();
+
+        gl_trace!(
+            "glVertexAttribPointer({}, {}, {:#x}, normalized {}, stride {}, offset 0)",
+            location,
+            count,
+            data_type,
+            normalized,
+            stride
+        );
         gl::VertexAttribPointer(
             location,
             count,
             data_type,
             normalized,
             stride,
-            0 as *const c_void,
+            std::ptr::null(),
         );
         gl::EnableVertexAttribArray(location);
+
+        Ok(())
+    }
+}
+
+// Debug builds check attribute locations against GL_MAX_VERTEX_ATTRIBS up front, GL would only
+// raise GL_INVALID_VALUE and leave the attribute unset. Release builds skip the query.
+unsafe fn check_attribute_location(location: u32) -> Result<(), BufferError> {
+    if cfg!(debug_assertions) {
+        let max = max_vertex_attribs();
+        if location >= max {
+            return Err(BufferError::AttributeOutOfRange { location, max });
+        }
+    }
+
+    Ok(())
+}
+
+// Layout builder for interleaved buffers. Attributes are laid out back to back in the order they
+// are added, so
+//
+//     vao.add_attribute(0, 3, gl::FLOAT).add_attribute(1, 3, gl::FLOAT).finish()?;
+//
+// reads position from bytes 0..12 and color from bytes 12..24 of every 24 byte vertex, out of the
+// buffer currently bound to GL_ARRAY_BUFFER.
+impl VertexArray {
+    pub fn add_attribute(&mut self, location: u32, count: i32, data_type: GLenum) -> &mut Self {
+        self.pending_attributes.push(VertexAttribute {
+            location,
+            count,
+            data_type,
+        });
+
+        self
+    }
+
+    pub unsafe fn finish(&mut self) -> Result<(), BufferError> {
+        debug::assert_context_current();
+        let attributes = std::mem::take(&mut self.pending_attributes);
+        let stride: usize = attributes
+            .iter()
+            .map(|attribute| attribute.count as usize * attribute_type_size(attribute.data_type))
+            .sum();
+
+        for attribute in &attributes {
+            check_attribute_location(attribute.location)?;
+        }
+
+        self.bind_uncached();
+        let mut offset = 0;
+
+        for attribute in &attributes {
+            gl_trace!(
+                "glVertexAttribPointer({}, {}, {:#x}, stride {}, offset {})",
+                attribute.location,
+                attribute.count,
+                attribute.data_type,
+                stride,
+                offset
+            );
+            gl::VertexAttribPointer(
+                attribute.location,
+                attribute.count,
+                attribute.data_type,
+                gl::FALSE,
+                stride as i32,
+                offset as *const c_void,
+            );
+            gl::EnableVertexAttribArray(attribute.location);
+
+            offset += attribute.count as usize * attribute_type_size(attribute.data_type);
+        }
+
+        Ok(())
+    }
+}
+
+// Attributes set up with `set_layout` or the layout builder advance once per vertex. Calling
+// `set_attribute_divisor(location, 1)` afterwards makes that attribute advance once per instance
+// instead, and `n` makes it advance every n instances. A divisor belongs to the attribute, not
+// the buffer, but `finish` gives every attribute in a build the same stride, so per-instance
+// attributes should go in their own buffer and their own `add_attribute`/`finish` pass.
+impl VertexArray {
+    pub unsafe fn set_attribute_divisor(&self, location: u32, divisor: u32) {
+        debug::assert_context_current();
+        self.bind_uncached();
+        gl_trace!("glVertexAttribDivisor({}, {})", location, divisor);
+        gl::VertexAttribDivisor(location, divisor);
+    }
+}
+
+impl VertexArray {
+    // Sets up a per-instance mat4 attribute from the buffer currently bound to GL_ARRAY_BUFFER.
+    // A mat4 takes four consecutive locations, one vec4 column each, so `location` through
+    // `location + 3` are all used and each advances once per instance.
+    pub unsafe fn set_mat4_instance_layout(
+        &self,
+        location: u32,
+        stride: i32,
+        offset: usize,
+    ) -> Result<(), BufferError> {
+        debug::assert_context_current();
+        check_attribute_location(location + 3)?;
+        self.bind_uncached();
+
+        let column_size = size_of::<[f32; 4]>();
+
+        for column in 0..4 {
+            let column_location = location + column;
+            let column_offset = offset + column as usize * column_size;
+            gl_trace!(
+                "glVertexAttribPointer({}, 4, GL_FLOAT, stride {}, offset {}), divisor 1",
+                column_location,
+                stride,
+                column_offset
+            );
+
+            gl::VertexAttribPointer(
+                column_location,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                column_offset as *const c_void,
+            );
+            gl::EnableVertexAttribArray(column_location);
+            gl::VertexAttribDivisor(column_location, 1);
+        }
+
+        Ok(())
+    }
+}
+
+impl VertexArray {
+    // Disables every attribute, clears instancing divisors and unbinds the element buffer, so a
+    // new layout can be applied without attributes from the previous one leaking through. Leaves
+    // this vertex array bound.
+    pub unsafe fn reset(&self) {
+        debug::assert_context_current();
+        self.bind_uncached();
+        gl_trace!("reset vertex array {}", self.id);
+
+        for location in 0..max_vertex_attribs() {
+            gl::DisableVertexAttribArray(location);
+            gl::VertexAttribDivisor(location, 0);
+        }
+
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        gl_trace!("glDeleteVertexArrays({})", self.id);
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn usage_maps_to_gl() {
+        assert_eq!(BufferUsage::StaticDraw.to_gl(), gl::STATIC_DRAW);
+        assert_eq!(BufferUsage::DynamicDraw.to_gl(), gl::DYNAMIC_DRAW);
+        assert_eq!(BufferUsage::StreamDraw.to_gl(), gl::STREAM_DRAW);
+    }
+
+    #[test]
+    fn resize_preserves_prefix() {
+        let spy = GlSpy::new();
+
         unsafe {
-            gl::DeleteVertexArrays(1, &mut self.id);
+            let buffer = Buffer::<u32>::new(gl::ELEMENT_ARRAY_BUFFER).unwrap();
+            buffer.set_data(&[1, 2, 3, 4], BufferUsage::DynamicDraw);
+
+            let before = spy.calls().len();
+            buffer.resize(32);
+            let element_binds = spy.calls()[before..]
+                .iter()
+                .filter(|call| {
+                    call.starts_with(&format!("glBindBuffer({:#x}", gl::ELEMENT_ARRAY_BUFFER))
+                })
+                .count();
+            assert_eq!(element_binds, 0);
+
+            assert_eq!(buffer.size(), 32);
+            assert_eq!(buffer.len(), 8);
+            assert_eq!(buffer.gl_size(), 32);
+            assert_eq!(buffer.gl_usage(), gl::DYNAMIC_DRAW);
+            assert_eq!(buffer.read_back(), Some(vec![1, 2, 3, 4, 0, 0, 0, 0]));
+
+            buffer.resize(8);
+            assert_eq!(buffer.len(), 2);
+            assert_eq!(buffer.read_back(), Some(vec![1, 2]));
         }
+
+        // Both scratch buffers and the resized one are deleted.
+        assert_eq!(spy.count("glGenBuffers"), 3);
+        assert_eq!(spy.count("glDeleteBuffers"), 3);
+    }
+
+    #[test]
+    fn shader_storage_reads_back() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let buffer = Buffer::<f32>::new_shader_storage().unwrap();
+            assert_eq!(buffer.read_back(), Some(vec![]));
+
+            buffer.set_data(&[0.5, 1.5, 2.5], BufferUsage::DynamicDraw);
+            buffer.bind_base(2);
+            assert_eq!(buffer.read_back(), Some(vec![0.5, 1.5, 2.5]));
+        }
+
+        let calls = spy.calls();
+        assert!(calls.contains(&format!(
+            "glBindBufferBase({:#x}, 2, 1)",
+            gl::SHADER_STORAGE_BUFFER
+        )));
+        assert!(calls.contains(&format!("glUnmapBuffer({:#x})", gl::SHADER_STORAGE_BUFFER)));
+    }
+
+    #[test]
+    fn mat4_instance_layout_sets_four_columns() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 16);
+
+        unsafe {
+            let vertex_array = VertexArray::new().unwrap();
+            vertex_array.set_mat4_instance_layout(4, 80, 16).unwrap();
+        }
+
+        let calls = spy.calls();
+        assert_eq!(calls[1], "glBindVertexArray(1)");
+        for column in 0..4 {
+            assert!(calls.contains(&format!(
+                "glVertexAttribPointer({}, 4, {:#x}, 80, {})",
+                4 + column,
+                gl::FLOAT,
+                16 + column * 16
+            )));
+            assert!(calls.contains(&format!("glEnableVertexAttribArray({})", 4 + column)));
+        }
+        let divisors: Vec<_> = calls
+            .iter()
+            .filter(|call| call.starts_with("glVertexAttribDivisor"))
+            .collect();
+        assert_eq!(
+            divisors,
+            [
+                "glVertexAttribDivisor(4, 1)",
+                "glVertexAttribDivisor(5, 1)",
+                "glVertexAttribDivisor(6, 1)",
+                "glVertexAttribDivisor(7, 1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn gl_size_and_usage_follow_set_data() {
+        let _spy = GlSpy::new();
+
+        unsafe {
+            let buffer = Buffer::<[f32; 3]>::new(gl::ARRAY_BUFFER).unwrap();
+            assert_eq!(buffer.gl_size(), 0);
+
+            buffer.set_data(&[[0.0; 3]; 4], BufferUsage::StaticDraw);
+            assert_eq!(buffer.gl_size(), 48);
+            assert_eq!(buffer.gl_size(), buffer.size());
+            assert_eq!(buffer.gl_usage(), gl::STATIC_DRAW);
+
+            buffer.set_data(&[[0.0; 3]], BufferUsage::StreamDraw);
+            assert_eq!(buffer.gl_size(), 12);
+            assert_eq!(buffer.gl_usage(), gl::STREAM_DRAW);
+        }
+    }
+
+    #[test]
+    fn reset_clears_every_attribute() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 8);
+
+        unsafe {
+            let vertex_array = VertexArray::new().unwrap();
+            vertex_array.set_mat4_instance_layout(0, 64, 0).unwrap();
+
+            let before = spy.calls().len();
+            vertex_array.reset();
+            let calls = &spy.calls()[before..];
+
+            assert_eq!(calls[0], "glBindVertexArray(1)");
+            for location in 0..8 {
+                assert!(calls.contains(&format!("glDisableVertexAttribArray({})", location)));
+                assert!(calls.contains(&format!("glVertexAttribDivisor({}, 0)", location)));
+            }
+            assert_eq!(
+                calls.last().unwrap(),
+                &format!("glBindBuffer({:#x}, 0)", gl::ELEMENT_ARRAY_BUFFER)
+            );
+        }
+    }
+
+    #[test]
+    fn update_sub_data_writes_in_place() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let buffer = Buffer::<u32>::new_uniform().unwrap();
+            buffer.set_data(&[1, 2, 3, 4], BufferUsage::DynamicDraw);
+            buffer.update_sub_data(4, &[20, 30]);
+
+            assert_eq!(buffer.len(), 4);
+            assert_eq!(buffer.read_back(), Some(vec![1, 20, 30, 4]));
+        }
+
+        // The store is only allocated once.
+        assert_eq!(spy.count("glBufferData"), 1);
+        assert!(spy
+            .calls()
+            .contains(&format!("glBufferSubData({:#x}, 4, 8)", gl::UNIFORM_BUFFER)));
+    }
+
+    // The check is a debug assertion.
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overruns")]
+    fn update_sub_data_checks_bounds() {
+        let _spy = GlSpy::new();
+
+        unsafe {
+            let buffer = Buffer::<u32>::new_uniform().unwrap();
+            buffer.set_data(&[1, 2], BufferUsage::DynamicDraw);
+            buffer.update_sub_data(4, &[3, 4]);
+        }
+    }
+
+    // Without a spy no context is current, test threads never make one.
+    #[test]
+    fn constructors_fail_without_a_context() {
+        unsafe {
+            assert!(matches!(
+                Buffer::<u8>::new(gl::ARRAY_BUFFER),
+                Err(BufferError::NoContext)
+            ));
+            assert!(matches!(VertexArray::new(), Err(BufferError::NoContext)));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn rejects_attribute_locations_out_of_range() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 16);
+
+        unsafe {
+            let mut vertex_array = VertexArray::new().unwrap();
+            assert!(matches!(
+                vertex_array.set_layout(16, 2, gl::FLOAT, gl::FALSE, 8),
+                Err(BufferError::AttributeOutOfRange {
+                    location: 16,
+                    max: 16
+                })
+            ));
+            assert!(matches!(
+                vertex_array.set_mat4_instance_layout(13, 64, 0),
+                Err(BufferError::AttributeOutOfRange {
+                    location: 16,
+                    max: 16
+                })
+            ));
+            vertex_array
+                .add_attribute(0, 3, gl::FLOAT)
+                .add_attribute(20, 3, gl::FLOAT);
+            assert!(matches!(
+                vertex_array.finish(),
+                Err(BufferError::AttributeOutOfRange {
+                    location: 20,
+                    max: 16
+                })
+            ));
+        }
+
+        assert_eq!(spy.count("glVertexAttribPointer"), 0);
+        assert_eq!(spy.count("glEnableVertexAttribArray"), 0);
+    }
+
+    #[test]
+    fn raw_ids_round_trip_without_deleting() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let buffer = Buffer::<[f32; 3]>::new(gl::ARRAY_BUFFER).unwrap();
+            buffer.set_data(&[[0.0; 3]; 4], BufferUsage::DynamicDraw);
+            let id = buffer.into_raw();
+
+            let buffer = Buffer::<[f32; 3]>::from_raw(id, gl::ARRAY_BUFFER);
+            assert_eq!(buffer.len(), 4);
+            assert_eq!(buffer.gl_usage(), gl::DYNAMIC_DRAW);
+            assert_eq!(buffer.into_raw(), id);
+
+            let vertex_array = VertexArray::new().unwrap();
+            let id = vertex_array.into_raw();
+            assert_eq!(VertexArray::from_raw(id).into_raw(), id);
+        }
+
+        assert!(!spy.calls().iter().any(|call| call.starts_with("glDelete")));
+    }
+
+    #[test]
+    fn odd_byte_counts_upload_exactly() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let bytes = Buffer::<u8>::new(gl::ARRAY_BUFFER).unwrap();
+            bytes.set_data(&[1, 2, 3, 4, 5, 6, 7], BufferUsage::StaticDraw);
+            assert_eq!((bytes.size(), bytes.len()), (7, 7));
+            assert_eq!(bytes.gl_size(), 7);
+
+            let colors = Buffer::<[u8; 3]>::new(gl::ARRAY_BUFFER).unwrap();
+            colors.set_data(&[[255, 0, 0]; 5], BufferUsage::StaticDraw);
+            assert_eq!((colors.size(), colors.len()), (15, 5));
+            assert_eq!(colors.gl_size(), 15);
+        }
+
+        let uploads: Vec<_> = spy
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("glBufferData"))
+            .collect();
+        assert_eq!(
+            uploads,
+            [
+                format!(
+                    "glBufferData({:#x}, 7, {:#x})",
+                    gl::ARRAY_BUFFER,
+                    gl::STATIC_DRAW
+                ),
+                format!(
+                    "glBufferData({:#x}, 15, {:#x})",
+                    gl::ARRAY_BUFFER,
+                    gl::STATIC_DRAW
+                ),
+            ]
+        );
     }
 }
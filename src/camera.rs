@@ -0,0 +1,192 @@
+use crate::math::perspective;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMovement {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+const WORLD_UP: [f32; 3] = [0.0, 1.0, 0.0];
+
+// Limits of the vertical field of view for scroll zooming, in degrees. The widest is also the
+// default.
+const MIN_FIELD_OF_VIEW: f32 = 1.0;
+const MAX_FIELD_OF_VIEW: f32 = 45.0;
+
+pub const NEAR_PLANE: f32 = 0.1;
+pub const FAR_PLANE: f32 = 100.0;
+
+// A first-person camera. Yaw and pitch are in degrees; a yaw of -90 looks down -Z, which is the
+// direction GL's clip space looks.
+pub struct Camera {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    // World units per second.
+    pub movement_speed: f32,
+    // Degrees per unit of mouse movement.
+    pub mouse_sensitivity: f32,
+    // Vertical field of view, in degrees.
+    pub fov: f32,
+    // Width over height of the framebuffer, kept up to date by `resize`.
+    aspect: f32,
+}
+
+impl Camera {
+    pub fn new(position: [f32; 3]) -> Self {
+        Self {
+            position,
+            yaw: -90.0,
+            pitch: 0.0,
+            movement_speed: 2.5,
+            mouse_sensitivity: 0.1,
+            fov: MAX_FIELD_OF_VIEW,
+            aspect: 1.0,
+        }
+    }
+
+    pub fn front(&self) -> [f32; 3] {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        normalize([
+            yaw.cos() * pitch.cos(),
+            pitch.sin(),
+            yaw.sin() * pitch.cos(),
+        ])
+    }
+
+    pub fn right(&self) -> [f32; 3] {
+        normalize(cross(self.front(), WORLD_UP))
+    }
+
+    pub fn up(&self) -> [f32; 3] {
+        cross(self.right(), self.front())
+    }
+
+    // Column-major, ready for `ShaderProgram::set_uniform_mat4`.
+    pub fn view_matrix(&self) -> [[f32; 4]; 4] {
+        let (front, right, up) = (self.front(), self.right(), self.up());
+        let eye = self.position;
+
+        [
+            [right[0], up[0], -front[0], 0.0],
+            [right[1], up[1], -front[1], 0.0],
+            [right[2], up[2], -front[2], 0.0],
+            [-dot(right, eye), -dot(up, eye), dot(front, eye), 1.0],
+        ]
+    }
+
+    pub fn process_keyboard(&mut self, direction: CameraMovement, dt: f32) {
+        let (axis, sign) = match direction {
+            CameraMovement::Forward => (self.front(), 1.0),
+            CameraMovement::Backward => (self.front(), -1.0),
+            CameraMovement::Right => (self.right(), 1.0),
+            CameraMovement::Left => (self.right(), -1.0),
+            CameraMovement::Up => (WORLD_UP, 1.0),
+            CameraMovement::Down => (WORLD_UP, -1.0),
+        };
+        let distance = self.movement_speed * dt * sign;
+
+        for (position, axis) in self.position.iter_mut().zip(axis) {
+            *position += axis * distance;
+        }
+    }
+
+    // `dy` is positive when the mouse moves up. Pitch stays within ±89° so the view never flips
+    // over the pole, where the look-at basis would degenerate.
+    pub fn process_mouse(&mut self, dx: f32, dy: f32) {
+        self.yaw += dx * self.mouse_sensitivity;
+        self.pitch = (self.pitch + dy * self.mouse_sensitivity).clamp(-89.0, 89.0);
+    }
+
+    // Zooms in for positive `delta` (scrolling up) by narrowing the field of view, one degree per
+    // scroll step.
+    pub fn process_scroll(&mut self, delta: f32) {
+        self.fov = (self.fov - delta).clamp(MIN_FIELD_OF_VIEW, MAX_FIELD_OF_VIEW);
+    }
+
+    // Turns the camera towards `target`, keeping the pitch within the same limits as mouse look.
+    pub fn look_at(&mut self, target: [f32; 3]) {
+        let direction = [
+            target[0] - self.position[0],
+            target[1] - self.position[1],
+            target[2] - self.position[2],
+        ];
+        if dot(direction, direction) == 0.0 {
+            return;
+        }
+
+        let direction = normalize(direction);
+        self.pitch = direction[1].asin().to_degrees().clamp(-89.0, 89.0);
+        self.yaw = direction[2].atan2(direction[0]).to_degrees();
+    }
+}
+
+impl Camera {
+    // Call with the new framebuffer size when it changes, so the projection isn't stretched. A
+    // minimized window's zero size is ignored.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    // Perspective projection with the camera's current field of view and aspect ratio.
+    pub fn projection_matrix(&self) -> [[f32; 4]; 4] {
+        perspective(self.fov.to_radians(), self.aspect, NEAR_PLANE, FAR_PLANE)
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resizing_updates_the_projection_aspect() {
+        let mut camera = Camera::new([0.0; 3]);
+        camera.resize(1600, 800);
+        assert_eq!(camera.aspect(), 2.0);
+
+        // The x scale is the y scale divided by the aspect ratio.
+        let projection = camera.projection_matrix();
+        assert!((projection[1][1] / projection[0][0] - 2.0).abs() < 1e-6);
+
+        camera.resize(0, 0);
+        assert_eq!(camera.aspect(), 2.0);
+    }
+
+    #[test]
+    fn looks_at_a_target() {
+        let mut camera = Camera::new([1.0, 2.0, 3.0]);
+        camera.look_at([4.0, 6.0, 3.0]);
+
+        let front = camera.front();
+        for (actual, expected) in front.iter().zip([0.6, 0.8, 0.0]) {
+            assert!((actual - expected).abs() < 1e-6, "{:?}", front);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use glam::Vec3;
+
+use crate::camera::Camera;
+
+// Where the camera is and what it looks at, `time` seconds into a `CameraPath`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+// A fly-through along keyframes, interpolated with Catmull-Rom splines so the camera passes
+// through every keyframe without stopping at it. Keyframes are sorted by time; the path starts
+// paused at the first one.
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+    time: f32,
+    playing: bool,
+    looping: bool,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let time = keyframes.first().map_or(0.0, |keyframe| keyframe.time);
+
+        Self {
+            keyframes,
+            time,
+            playing: false,
+            looping: false,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // A looping path starts over after its last keyframe, otherwise it stops there.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    fn start(&self) -> f32 {
+        self.keyframes.first().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    fn end(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // Advances the path by the frame time while it is playing, and moves `camera` to it.
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) {
+        if self.playing {
+            self.time += dt;
+            let (start, end) = (self.start(), self.end());
+            if self.time >= end {
+                if self.looping && end > start {
+                    self.time = start + (self.time - start) % (end - start);
+                } else {
+                    self.time = end;
+                    self.playing = false;
+                }
+            }
+        }
+
+        if let Some((position, target)) = self.evaluate(self.time) {
+            camera.position = position;
+            camera.look_at(target);
+        }
+    }
+
+    // The position and target at `time`, held at the first and last keyframes outside of the
+    // path. None for a path without keyframes.
+    pub fn evaluate(&self, time: f32) -> Option<([f32; 3], [f32; 3])> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(last + 1);
+        if next == 0 || next > last {
+            let keyframe = self.keyframes[next.min(last)];
+            return Some((keyframe.position, keyframe.target));
+        }
+
+        // The segment from `next - 1` to `next`, shaped by the keyframes on either side of it.
+        let (from, to) = (self.keyframes[next - 1], self.keyframes[next]);
+        let before = self.keyframes[next.saturating_sub(2)];
+        let after = self.keyframes[(next + 1).min(last)];
+        let t = (time - from.time) / (to.time - from.time);
+        let spline = |select: fn(&CameraKeyframe) -> [f32; 3]| {
+            catmull_rom(
+                Vec3::from(select(&before)),
+                Vec3::from(select(&from)),
+                Vec3::from(select(&to)),
+                Vec3::from(select(&after)),
+                t,
+            )
+            .to_array()
+        };
+
+        Some((
+            spline(|keyframe| keyframe.position),
+            spline(|keyframe| keyframe.target),
+        ))
+    }
+}
+
+// Uniform Catmull-Rom between `p1` (t = 0) and `p2` (t = 1), with `p0` and `p3` setting the
+// tangents.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> CameraPath {
+        CameraPath::new(vec![
+            CameraKeyframe {
+                time: 0.0,
+                position: [0.0, 0.0, 3.0],
+                target: [0.0; 3],
+            },
+            CameraKeyframe {
+                time: 2.0,
+                position: [3.0, 1.0, 0.0],
+                target: [0.0; 3],
+            },
+            CameraKeyframe {
+                time: 4.0,
+                position: [0.0, 2.0, -3.0],
+                target: [0.0, 1.0, 0.0],
+            },
+        ])
+    }
+
+    #[test]
+    fn passes_through_each_keyframe() {
+        let path = path();
+
+        assert_eq!(path.evaluate(2.0), Some(([3.0, 1.0, 0.0], [0.0; 3])));
+        assert_eq!(
+            path.evaluate(4.0),
+            Some(([0.0, 2.0, -3.0], [0.0, 1.0, 0.0]))
+        );
+        // Held outside the path.
+        assert_eq!(path.evaluate(-1.0), path.evaluate(0.0));
+        assert_eq!(path.evaluate(9.0), path.evaluate(4.0));
+    }
+
+    #[test]
+    fn plays_pauses_and_loops() {
+        let mut path = path();
+        let mut camera = Camera::new([0.0; 3]);
+
+        path.update(1.0, &mut camera);
+        assert_eq!(path.time(), 0.0);
+        assert_eq!(camera.position, [0.0, 0.0, 3.0]);
+
+        path.play();
+        path.update(2.0, &mut camera);
+        assert_eq!(camera.position, [3.0, 1.0, 0.0]);
+
+        path.pause();
+        path.update(1.0, &mut camera);
+        assert_eq!(path.time(), 2.0);
+
+        path.set_looping(true);
+        path.play();
+        path.update(3.0, &mut camera);
+        assert_eq!(path.time(), 1.0);
+
+        path.set_looping(false);
+        path.update(5.0, &mut camera);
+        assert_eq!(path.time(), 4.0);
+        assert!(!path.is_playing());
+    }
+}
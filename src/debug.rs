@@ -0,0 +1,188 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_char,
+    slice,
+};
+
+use gl::types::*;
+
+use crate::error::Error;
+
+#[cfg(test)]
+thread_local! {
+    // Set while a `gl_spy::GlSpy` stands in for a context.
+    pub(crate) static FAKE_CONTEXT: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn glfw_context_current() -> bool {
+    #[cfg(test)]
+    if FAKE_CONTEXT.with(|fake| fake.get()) {
+        return true;
+    }
+
+    !unsafe { glfw::ffi::glfwGetCurrentContext() }.is_null()
+}
+
+// Called at the top of the GL wrappers so that using them without a current context, or before
+// `gl::load_with`, fails with an explanation instead of a crash inside the driver. Compiled out
+// of release builds.
+#[track_caller]
+pub fn assert_context_current() {
+    if cfg!(debug_assertions) {
+        assert!(
+            glfw_context_current(),
+            "No OpenGL context is current on this thread, call window.make_current() first"
+        );
+        assert!(
+            gl::GetError::is_loaded(),
+            "OpenGL functions are not loaded, call gl::load_with after making the context current"
+        );
+    }
+}
+
+// Whether a context is current on this thread with GL functions loaded, for code that has to
+// cope without one, like destructors, which must not panic.
+pub fn context_is_current() -> bool {
+    glfw_context_current() && gl::GetError::is_loaded()
+}
+
+// Drains GL's error queue, printing every pending error along with `label`, which should say
+// what was just done. GL only records that something went wrong since the last check, so calls
+// placed right after a suspect operation give the most useful labels. Prefer `gl_check!`, which
+// is compiled out of release builds. Returns the first of the errors, for callers that want to
+// stop on them.
+pub fn check_gl_error(label: &str) -> Result<(), Error> {
+    assert_context_current();
+    let mut first = None;
+
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
+        }
+        first.get_or_insert(error);
+
+        eprintln!(
+            "GL error after {}: {} ({:#x})",
+            label,
+            gl_error_name(error),
+            error
+        );
+    }
+
+    match first {
+        Some(error) => Err(Error::Gl(error)),
+        None => Ok(()),
+    }
+}
+
+pub fn gl_error_name(error: GLenum) -> &'static str {
+    match error {
+        gl::INVALID_ENUM => "GL_INVALID_ENUM",
+        gl::INVALID_VALUE => "GL_INVALID_VALUE",
+        gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        _ => "unknown error",
+    }
+}
+
+#[macro_export]
+macro_rules! gl_check {
+    ($label:expr) => {
+        if cfg!(debug_assertions) {
+            $crate::debug::check_gl_error($label).ok();
+        }
+    };
+}
+
+// Has the driver report errors, misuse and performance warnings as they happen, through
+// `debug_message_callback`, when GL_KHR_debug is available. Messages are delivered
+// synchronously, so a breakpoint in the callback stops on the offending GL call. Returns whether
+// debug output was enabled; without the extension this does nothing.
+//
+// Drivers may only produce messages for contexts created with the OpenGlDebugContext hint.
+pub unsafe fn enable_debug_output() -> bool {
+    assert_context_current();
+    if !has_extension("GL_KHR_debug") || !gl::DebugMessageCallback::is_loaded() {
+        return false;
+    }
+
+    gl::Enable(gl::DEBUG_OUTPUT);
+    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    gl::DebugMessageCallback(Some(debug_message_callback), std::ptr::null());
+
+    // Notifications are mostly drivers describing where they put buffers, which floods the
+    // output every frame.
+    gl::DebugMessageControl(
+        gl::DONT_CARE,
+        gl::DONT_CARE,
+        gl::DEBUG_SEVERITY_NOTIFICATION,
+        0,
+        std::ptr::null(),
+        gl::FALSE,
+    );
+
+    true
+}
+
+pub unsafe fn has_extension(name: &str) -> bool {
+    let mut count: GLint = 0;
+    gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+
+    (0..count.max(0) as GLuint).any(|index| {
+        let extension = gl::GetStringi(gl::EXTENSIONS, index);
+        !extension.is_null()
+            && CStr::from_ptr(extension as *const c_char).to_bytes() == name.as_bytes()
+    })
+}
+
+extern "system" fn debug_message_callback(
+    source: GLenum,
+    gltype: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let message = unsafe {
+        if length >= 0 {
+            String::from_utf8_lossy(slice::from_raw_parts(message as *const u8, length as usize))
+        } else {
+            CStr::from_ptr(message).to_string_lossy()
+        }
+    };
+
+    let source = match source {
+        gl::DEBUG_SOURCE_API => "API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "third party",
+        gl::DEBUG_SOURCE_APPLICATION => "application",
+        _ => "other",
+    };
+    let gltype = match gltype {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance",
+        gl::DEBUG_TYPE_MARKER => "marker",
+        _ => "other",
+    };
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH => "high",
+        gl::DEBUG_SEVERITY_MEDIUM => "medium",
+        gl::DEBUG_SEVERITY_LOW => "low",
+        gl::DEBUG_SEVERITY_NOTIFICATION => "notification",
+        _ => "unknown",
+    };
+
+    eprintln!(
+        "GL debug [{}] {} {} ({}): {}",
+        severity, source, gltype, id, message
+    );
+}
@@ -0,0 +1,89 @@
+use gl::types::*;
+use thiserror::Error;
+
+use crate::buffers::BufferError;
+use crate::framebuffer::FramebufferError;
+use crate::mesh::ObjError;
+use crate::shaders::ShaderError;
+use crate::texture::TextureError;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error{"{0}"}]
+    Shader(#[from] ShaderError),
+    #[error{"{0}"}]
+    TextureLoad(#[from] TextureError),
+    #[error{"{0}"}]
+    Buffer(#[from] BufferError),
+    #[error{"{0}"}]
+    Framebuffer(#[from] FramebufferError),
+    #[error{"{0}"}]
+    ModelLoad(#[from] ObjError),
+    #[error{"{0}"}]
+    Io(#[from] std::io::Error),
+    #[error("Failed to initialize GLFW: {0}")]
+    Init(#[from] glfw::InitError),
+    #[error("Failed to create GLFW window")]
+    WindowCreation,
+    // The first error `debug::check_gl_error` found.
+    #[error("OpenGL error {0:#x}")]
+    Gl(GLenum),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_from_source_errors() {
+        let error = Error::from(ShaderError::UniformNotFound("uTime".to_string()));
+        assert!(matches!(
+            error,
+            Error::Shader(ShaderError::UniformNotFound(_))
+        ));
+
+        let image_error = image::open("does/not/exist.png").unwrap_err();
+        let error = Error::from(TextureError::from(image_error));
+        assert!(matches!(error, Error::TextureLoad(_)));
+
+        let error = Error::from(BufferError::CreationFailed(gl::ARRAY_BUFFER));
+        assert!(matches!(error, Error::Buffer(_)));
+
+        let error = Error::from(FramebufferError::Incomplete(
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT,
+        ));
+        assert!(matches!(error, Error::Framebuffer(_)));
+
+        let error = Error::from(ObjError::Parse {
+            line: 3,
+            message: "face with 2 corners, at least 3 are needed".to_string(),
+        });
+        assert!(matches!(
+            error,
+            Error::ModelLoad(ObjError::Parse { line: 3, .. })
+        ));
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let error = Error::from(io_error);
+        assert!(matches!(error, Error::Io(_)));
+
+        let error = Error::from(glfw::InitError::Internal);
+        assert!(matches!(error, Error::Init(glfw::InitError::Internal)));
+    }
+
+    #[test]
+    fn displays_gl_errors() {
+        assert_eq!(
+            Error::Gl(gl::INVALID_ENUM).to_string(),
+            "OpenGL error 0x500"
+        );
+        assert_eq!(
+            Error::WindowCreation.to_string(),
+            "Failed to create GLFW window"
+        );
+        assert_eq!(
+            Error::from(BufferError::CreationFailed(gl::ARRAY_BUFFER)).to_string(),
+            "Failed to create GL object of type 0x8892"
+        );
+    }
+}
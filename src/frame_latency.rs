@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+
+use gl::types::*;
+
+use crate::debug;
+
+// Limits how many frames the CPU may run ahead of the GPU, which is what makes input feel laggy
+// when the driver queues several frames behind `swap_buffers`.
+//
+// Neither core GL nor GLFW expose the platform "maximum frame latency" knobs (DXGI's
+// SetMaximumFrameLatency, NV_swap_group and friends), so this uses what does work everywhere:
+// a fence is inserted after every swap and the CPU waits on the fence from `max_frames` frames
+// ago before starting a new one. With vsync on the driver would otherwise happily buffer two or
+// three frames; with vsync off the limiter mostly bounds how far ahead the CPU submits.
+//
+// A latency of 0 leaves queuing entirely to the driver.
+pub struct FrameLatencyLimiter {
+    max_frames: usize,
+    fences: VecDeque<GLsync>,
+}
+
+impl FrameLatencyLimiter {
+    pub fn new(max_frames: usize) -> Self {
+        let mut limiter = Self {
+            max_frames: 0,
+            fences: VecDeque::new(),
+        };
+        limiter.set_frame_latency(max_frames);
+
+        limiter
+    }
+
+    pub fn set_frame_latency(&mut self, max_frames: usize) {
+        if max_frames > 0 && !gl::FenceSync::is_loaded() {
+            eprintln!("Fence sync objects are not available, frame latency is left to the driver");
+            self.max_frames = 0;
+            return;
+        }
+
+        self.max_frames = max_frames;
+    }
+
+    pub fn frame_latency(&self) -> usize {
+        self.max_frames
+    }
+
+    // Call right after `swap_buffers`.
+    pub unsafe fn end_frame(&mut self) {
+        debug::assert_context_current();
+        if self.max_frames == 0 {
+            self.delete_fences();
+            return;
+        }
+
+        self.fences
+            .push_back(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+
+        while self.fences.len() > self.max_frames {
+            if let Some(fence) = self.fences.pop_front() {
+                gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, GLuint64::MAX);
+                gl::DeleteSync(fence);
+            }
+        }
+    }
+
+    unsafe fn delete_fences(&mut self) {
+        for fence in self.fences.drain(..) {
+            gl::DeleteSync(fence);
+        }
+    }
+}
+
+impl Drop for FrameLatencyLimiter {
+    fn drop(&mut self) {
+        unsafe { self.delete_fences() }
+    }
+}
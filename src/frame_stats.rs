@@ -0,0 +1,103 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::profiling::CpuStats;
+
+// Frame rate and frame time averaged over roughly one second, fed with each frame's delta time.
+// Averaging smooths out single slow frames, which would make an instantaneous counter unreadable.
+// The CPU scope timings of each frame (see `profiling`) are averaged over the same interval.
+#[derive(Default)]
+pub struct FrameStats {
+    // Time, frame count and scope timings accumulated since the averages were last updated.
+    elapsed: f32,
+    frames: u32,
+    cpu_totals: HashMap<&'static str, Duration>,
+    avg_fps: f32,
+    avg_frame_ms: f32,
+    // Sorted by scope name.
+    avg_cpu_ms: Vec<(&'static str, f32)>,
+}
+
+// How often the averages are updated, in seconds.
+const UPDATE_INTERVAL: f32 = 1.0;
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds the scope timings of a frame, before it is `record`ed.
+    pub fn record_cpu(&mut self, cpu: &CpuStats) {
+        for (name, time) in cpu.iter() {
+            *self.cpu_totals.entry(name).or_default() += time;
+        }
+    }
+
+    // Adds a frame that took `dt` seconds. Returns true when the averages were just updated.
+    pub fn record(&mut self, dt: f32) -> bool {
+        self.elapsed += dt;
+        self.frames += 1;
+
+        if self.elapsed < UPDATE_INTERVAL {
+            return false;
+        }
+
+        self.avg_fps = self.frames as f32 / self.elapsed;
+        self.avg_frame_ms = self.elapsed * 1000.0 / self.frames as f32;
+
+        let frames = self.frames as f32;
+        self.avg_cpu_ms = self
+            .cpu_totals
+            .drain()
+            .map(|(name, total)| (name, total.as_secs_f32() * 1000.0 / frames))
+            .collect();
+        self.avg_cpu_ms.sort_by_key(|(name, _)| *name);
+        self.elapsed = 0.0;
+        self.frames = 0;
+
+        true
+    }
+}
+
+impl FrameStats {
+    // Both are 0 until the first full interval has passed.
+    pub fn avg_fps(&self) -> f32 {
+        self.avg_fps
+    }
+
+    pub fn avg_frame_ms(&self) -> f32 {
+        self.avg_frame_ms
+    }
+
+    // Average time per frame spent in each CPU scope, in milliseconds.
+    pub fn avg_cpu_ms(&self) -> &[(&'static str, f32)] {
+        &self.avg_cpu_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiling;
+
+    #[test]
+    fn averages_cpu_scopes_per_frame() {
+        let mut stats = FrameStats::new();
+        profiling::end_frame();
+
+        for _ in 0..2 {
+            {
+                cpu_scope!("frame");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            stats.record_cpu(&profiling::end_frame());
+            stats.record(UPDATE_INTERVAL / 2.0);
+        }
+
+        assert_eq!(stats.avg_fps(), 2.0);
+        let [(name, ms)] = stats.avg_cpu_ms() else {
+            panic!("expected one scope, got {:?}", stats.avg_cpu_ms());
+        };
+        assert_eq!(*name, "frame");
+        assert!(*ms >= 1.0, "{}", ms);
+    }
+}
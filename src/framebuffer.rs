@@ -0,0 +1,286 @@
+use std::marker::PhantomData;
+
+use gl::types::*;
+use thiserror::Error;
+
+use crate::debug;
+use crate::texture::Texture;
+
+#[derive(Debug, Error)]
+pub enum FramebufferError {
+    #[error("Framebuffer is incomplete: status {0:#x}")]
+    Incomplete(GLenum),
+}
+
+// An offscreen RGBA8 color + 24-bit depth render target, backed by renderbuffers, or with its
+// color in a texture for `with_color_texture`.
+//
+// With `samples` > 0 both buffers are multisampled. A multisampled framebuffer can't be sampled
+// or read from directly, it has to be resolved with `blit_to` into a single-sampled one (or the
+// window) first. Multisampling the window itself doesn't need any of this, only
+// `WindowConfig::samples`.
+pub struct Framebuffer {
+    id: u32,
+    // The color renderbuffer, 0 when the color goes to `color_texture` instead.
+    color: u32,
+    color_texture: Option<Texture>,
+    depth: u32,
+    width: i32,
+    height: i32,
+    samples: i32,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Framebuffer {
+    // Leaves the new framebuffer bound to GL_FRAMEBUFFER.
+    pub unsafe fn new(width: i32, height: i32, samples: i32) -> Result<Self, FramebufferError> {
+        let mut framebuffer = Self::generate(width, height, samples);
+        framebuffer.color = framebuffer.attach_renderbuffer(gl::RGBA8, gl::COLOR_ATTACHMENT0);
+
+        framebuffer.finish()
+    }
+
+    // Like `new` without multisampling, but renders its color into a texture, which later passes
+    // can sample or `into_color_texture` can take over.
+    pub unsafe fn with_color_texture(width: i32, height: i32) -> Result<Self, FramebufferError> {
+        let mut framebuffer = Self::generate(width, height, 0);
+        let texture = Texture::empty(width as u32, height as u32);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture.id(),
+            0,
+        );
+        framebuffer.color_texture = Some(texture);
+
+        framebuffer.finish()
+    }
+
+    unsafe fn generate(width: i32, height: i32, samples: i32) -> Self {
+        debug::assert_context_current();
+        let mut framebuffer = Self {
+            id: 0,
+            color: 0,
+            color_texture: None,
+            depth: 0,
+            width,
+            height,
+            samples,
+            _not_send: PhantomData,
+        };
+
+        gl::GenFramebuffers(1, &mut framebuffer.id);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.id);
+        gl_trace!(
+            "glGenFramebuffers -> framebuffer {}, {}x{}, {} samples",
+            framebuffer.id,
+            width,
+            height,
+            samples
+        );
+
+        framebuffer
+    }
+
+    // Adds the depth buffer once the color is attached.
+    unsafe fn finish(mut self) -> Result<Self, FramebufferError> {
+        self.depth = self.attach_renderbuffer(gl::DEPTH_COMPONENT24, gl::DEPTH_ATTACHMENT);
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(FramebufferError::Incomplete(status));
+        }
+
+        Ok(self)
+    }
+
+    unsafe fn attach_renderbuffer(&self, format: GLenum, attachment: GLenum) -> u32 {
+        let mut renderbuffer = 0;
+        gl::GenRenderbuffers(1, &mut renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+
+        if self.samples > 0 {
+            gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                self.samples,
+                format,
+                self.width,
+                self.height,
+            );
+        } else {
+            gl::RenderbufferStorage(gl::RENDERBUFFER, format, self.width, self.height);
+        }
+
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, renderbuffer);
+        renderbuffer
+    }
+}
+
+impl Framebuffer {
+    pub unsafe fn bind(&self) {
+        debug::assert_context_current();
+        gl_trace!("glBindFramebuffer(GL_FRAMEBUFFER, {})", self.id);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+    }
+
+    // Copies the color buffer into `target`, or the window's framebuffer for None, resolving the
+    // samples when this framebuffer is multisampled. The target has to be the same size for a
+    // resolve. Leaves the target bound as the draw framebuffer.
+    pub unsafe fn blit_to(&self, target: Option<&Framebuffer>) {
+        debug::assert_context_current();
+        let target_id = target.map_or(0, |target| target.id);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_id);
+        gl_trace!("glBlitFramebuffer({} -> {})", self.id, target_id);
+        gl::BlitFramebuffer(
+            0,
+            0,
+            self.width,
+            self.height,
+            0,
+            0,
+            self.width,
+            self.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+    }
+
+    // Makes the window's framebuffer the target for drawing and reading again.
+    pub unsafe fn bind_default() {
+        debug::assert_context_current();
+        gl_trace!("glBindFramebuffer(GL_FRAMEBUFFER, 0)");
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    // None unless created with `with_color_texture`.
+    pub fn color_texture(&self) -> Option<&Texture> {
+        self.color_texture.as_ref()
+    }
+
+    // Deletes the framebuffer but keeps its color texture, if it has one.
+    pub fn into_color_texture(mut self) -> Option<Texture> {
+        self.color_texture.take()
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        // Without a context there is nothing left to delete, the framebuffer went with it.
+        if !debug::context_is_current() {
+            return;
+        }
+
+        gl_trace!("glDeleteFramebuffers({})", self.id);
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteRenderbuffers(2, [self.color, self.depth].as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::FAKE_CONTEXT;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn multisampled_framebuffers_use_multisampled_storage() {
+        let spy = GlSpy::new();
+
+        unsafe { Framebuffer::new(64, 32, 4).unwrap() };
+
+        assert_eq!(spy.count("glRenderbufferStorage"), 0);
+        let storage: Vec<_> = spy
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("glRenderbufferStorageMultisample"))
+            .collect();
+        assert_eq!(
+            storage,
+            [
+                format!(
+                    "glRenderbufferStorageMultisample({:#x}, 4, {:#x}, 64, 32)",
+                    gl::RENDERBUFFER,
+                    gl::RGBA8
+                ),
+                format!(
+                    "glRenderbufferStorageMultisample({:#x}, 4, {:#x}, 64, 32)",
+                    gl::RENDERBUFFER,
+                    gl::DEPTH_COMPONENT24
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn blits_from_read_to_draw_framebuffer() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let source = Framebuffer::new(64, 32, 4).unwrap();
+            let target = Framebuffer::new(64, 32, 0).unwrap();
+
+            let before = spy.calls().len();
+            source.blit_to(Some(&target));
+            source.blit_to(None);
+            let calls = &spy.calls()[before..];
+
+            let blit = format!(
+                "glBlitFramebuffer(0, 0, 64, 32 -> 0, 0, 64, 32, {:#x}, {:#x})",
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST
+            );
+            assert_eq!(
+                calls,
+                [
+                    format!(
+                        "glBindFramebuffer({:#x}, {})",
+                        gl::READ_FRAMEBUFFER,
+                        source.id()
+                    ),
+                    format!(
+                        "glBindFramebuffer({:#x}, {})",
+                        gl::DRAW_FRAMEBUFFER,
+                        target.id()
+                    ),
+                    blit.clone(),
+                    format!(
+                        "glBindFramebuffer({:#x}, {})",
+                        gl::READ_FRAMEBUFFER,
+                        source.id()
+                    ),
+                    format!("glBindFramebuffer({:#x}, 0)", gl::DRAW_FRAMEBUFFER),
+                    blit,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn dropping_without_a_context_deletes_nothing() {
+        let spy = GlSpy::new();
+        let framebuffer = unsafe { Framebuffer::new(4, 4, 0).unwrap() };
+
+        FAKE_CONTEXT.with(|fake| fake.set(false));
+        drop(framebuffer);
+
+        assert_eq!(spy.count("glDeleteFramebuffers"), 0);
+        assert_eq!(spy.count("glDeleteRenderbuffers"), 0);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::render_state::RenderState;
+use crate::shaders::{ShaderError, ShaderProgram};
+use crate::texture::Texture;
+
+// Trades speed for how much aliasing the FXAA pass finds and smooths out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FxaaQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl FxaaQuality {
+    // Smallest contrast around a pixel, relative to its brightest neighbour, that counts as an
+    // edge. Lower values catch fainter edges but soften more texture detail.
+    pub fn edge_threshold(self) -> f32 {
+        match self {
+            FxaaQuality::Low => 0.25,
+            FxaaQuality::Medium => 0.166,
+            FxaaQuality::High => 0.125,
+        }
+    }
+
+    // Texels walked along an edge in each direction to find its ends. Longer walks smooth
+    // shallower edges.
+    pub fn search_steps(self) -> i32 {
+        match self {
+            FxaaQuality::Low => 4,
+            FxaaQuality::Medium => 8,
+            FxaaQuality::High => 12,
+        }
+    }
+}
+
+// Fast approximate antialiasing as a post-process, a cheaper alternative to MSAA that smooths
+// every edge in the rendered image, including those inside textures and alpha-tested geometry.
+// The pass is shaders/fxaa.frag run over the scene's color texture (see
+// `Framebuffer::with_color_texture`) on a quad covering the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fxaa {
+    pub enabled: bool,
+    pub quality: FxaaQuality,
+}
+
+impl Fxaa {
+    pub fn new(quality: FxaaQuality) -> Self {
+        Self {
+            enabled: true,
+            quality,
+        }
+    }
+
+    // Binds `program`, a program using shaders/fxaa.frag, with `scene` on texture unit 0, and
+    // uploads the quality settings. Drawing the screen quad with texture coordinates 0..1 then
+    // runs the pass.
+    pub unsafe fn prepare(
+        &self,
+        render_state: &mut RenderState,
+        program: &ShaderProgram,
+        scene: &Texture,
+    ) -> Result<(), ShaderError> {
+        render_state.use_program(program.id);
+        scene.bind(render_state, 0);
+
+        program.set_uniform_i32("uScene", 0)?;
+        program.set_uniform_f32("uEdgeThreshold", self.quality.edge_threshold())?;
+        program.set_uniform_i32("uSearchSteps", self.quality.search_steps())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn prepare_binds_the_scene_and_uploads_the_quality() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        let fxaa = Fxaa::new(FxaaQuality::Low);
+
+        unsafe {
+            let program = ShaderProgram::from_raw(4);
+            let scene = Texture::from_raw(9, 640, 480);
+            fxaa.prepare(&mut render_state, &program, &scene).unwrap();
+            scene.into_raw();
+            program.into_raw();
+        }
+
+        let calls: Vec<_> = spy
+            .calls()
+            .into_iter()
+            .filter(|call| !call.starts_with("glGetUniformLocation"))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "glUseProgram(4)".to_string(),
+                format!("glActiveTexture({:#x})", gl::TEXTURE0),
+                format!("glBindTexture({:#x}, 9)", gl::TEXTURE_2D),
+                "glUniform1i(0, 0)".to_string(),
+                "glUniform1f(0, 0.25)".to_string(),
+                "glUniform1i(0, 4)".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,932 @@
+// Stands in for a GL context in unit tests: loads recording versions of the GL functions the
+// wrappers call, so tests can check which calls an operation makes without a window. Only
+// compiled for tests.
+//
+// GL function pointers are global, so tests using a `GlSpy` run one at a time.
+use std::{
+    collections::{HashMap, HashSet},
+    os::raw::c_void,
+    sync::{Mutex, MutexGuard},
+};
+
+use gl::types::*;
+
+use crate::debug::FAKE_CONTEXT;
+
+static LOCK: Mutex<()> = Mutex::new(());
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+#[derive(Default)]
+struct State {
+    calls: Vec<String>,
+    // Answers for glGetIntegerv, 0 for anything not in here. glGetFramebufferAttachmentParameteriv
+    // answers from here too, by pname.
+    integers: HashMap<GLenum, Vec<GLint>>,
+    // Capabilities turned on with glEnable.
+    enabled: HashSet<GLenum>,
+    // Ids handed out by the glGen* functions start at 1, like a real context's.
+    last_id: GLuint,
+    // Buffer stores by id and the buffer bound to each target, so data can be read back.
+    buffers: HashMap<GLuint, FakeBuffer>,
+    bound_buffers: HashMap<GLenum, GLuint>,
+    // Programs linked with glLinkProgram or loaded from an accepted glProgramBinary.
+    linked_programs: HashSet<GLuint>,
+}
+
+// The binary every linked program reports, and the only one glProgramBinary accepts.
+pub(crate) const PROGRAM_BINARY_FORMAT: GLenum = 0x8f00;
+pub(crate) const PROGRAM_BINARY: &[u8] = b"spy program binary";
+
+#[derive(Default)]
+struct FakeBuffer {
+    data: Vec<u8>,
+    usage: GLenum,
+}
+
+impl State {
+    fn next_id(&mut self) -> GLuint {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn bound_buffer(&mut self, target: GLenum) -> &mut FakeBuffer {
+        let id = self.bound_buffers.get(&target).copied().unwrap_or(0);
+        self.buffers
+            .get_mut(&id)
+            .unwrap_or_else(|| panic!("no buffer bound to {:#x}", target))
+    }
+}
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state = STATE.lock().unwrap_or_else(|err| err.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+fn record(call: String) {
+    with_state(|state| state.calls.push(call));
+}
+
+pub(crate) struct GlSpy {
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl GlSpy {
+    pub fn new() -> Self {
+        let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        *STATE.lock().unwrap_or_else(|err| err.into_inner()) = Some(State::default());
+        load(true);
+        FAKE_CONTEXT.with(|fake| fake.set(true));
+
+        Self { _lock: lock }
+    }
+
+    // Makes glGetIntegerv answer `value` for `pname`.
+    pub fn set_integer(&self, pname: GLenum, value: GLint) {
+        self.set_integers(pname, &[value]);
+    }
+
+    // For queries with several values, like GL_VIEWPORT.
+    pub fn set_integers(&self, pname: GLenum, values: &[GLint]) {
+        with_state(|state| state.integers.insert(pname, values.to_vec()));
+    }
+
+    // Every call made so far, formatted like "glUseProgram(3)".
+    pub fn calls(&self) -> Vec<String> {
+        with_state(|state| state.calls.clone())
+    }
+
+    // How many times `function` (e.g. "glUseProgram") was called.
+    pub fn count(&self, function: &str) -> usize {
+        with_state(|state| {
+            state
+                .calls
+                .iter()
+                .filter(|call| call.split('(').next() == Some(function))
+                .count()
+        })
+    }
+}
+
+impl Drop for GlSpy {
+    fn drop(&mut self) {
+        FAKE_CONTEXT.with(|fake| fake.set(false));
+        load(false);
+    }
+}
+
+macro_rules! spies {
+    ($($function:ident => $spy:ident),* $(,)?) => {
+        // Loads the spies, or unloads every function they replace.
+        fn load(spies: bool) {
+            $(
+                gl::$function::load_with(|_| {
+                    if spies {
+                        $spy as *const c_void
+                    } else {
+                        std::ptr::null()
+                    }
+                });
+            )*
+        }
+    };
+}
+
+spies! {
+    ActiveTexture => active_texture,
+    AttachShader => attach_shader,
+    BindBuffer => bind_buffer,
+    BindBufferBase => bind_buffer_base,
+    BindFramebuffer => bind_framebuffer,
+    BindRenderbuffer => bind_renderbuffer,
+    BindTexture => bind_texture,
+    BindVertexArray => bind_vertex_array,
+    BlendEquationSeparate => blend_equation_separate,
+    BlendFuncSeparate => blend_func_separate,
+    BlitFramebuffer => blit_framebuffer,
+    BufferData => buffer_data,
+    BufferSubData => buffer_sub_data,
+    CheckFramebufferStatus => check_framebuffer_status,
+    ClearDepth => clear_depth,
+    ClipControl => clip_control,
+    CompileShader => compile_shader,
+    CopyBufferSubData => copy_buffer_sub_data,
+    CreateProgram => create_program,
+    CreateShader => create_shader,
+    DeleteBuffers => delete_buffers,
+    DeleteFramebuffers => delete_framebuffers,
+    DeleteProgram => delete_program,
+    DeleteRenderbuffers => delete_renderbuffers,
+    DeleteShader => delete_shader,
+    DeleteTextures => delete_textures,
+    DeleteVertexArrays => delete_vertex_arrays,
+    DepthFunc => depth_func,
+    DepthMask => depth_mask,
+    Disable => disable,
+    DisableVertexAttribArray => disable_vertex_attrib_array,
+    DrawArrays => draw_arrays,
+    DrawArraysInstanced => draw_arrays_instanced,
+    DrawElements => draw_elements,
+    DrawElementsInstanced => draw_elements_instanced,
+    Enable => enable,
+    EnableVertexAttribArray => enable_vertex_attrib_array,
+    FlushMappedBufferRange => flush_mapped_buffer_range,
+    FramebufferRenderbuffer => framebuffer_renderbuffer,
+    FramebufferTexture2D => framebuffer_texture_2d,
+    FrontFace => front_face,
+    GenBuffers => gen_buffers,
+    GenFramebuffers => gen_framebuffers,
+    GenRenderbuffers => gen_renderbuffers,
+    GenTextures => gen_textures,
+    GenerateMipmap => generate_mipmap,
+    GenVertexArrays => gen_vertex_arrays,
+    GetBufferParameteriv => get_buffer_parameteriv,
+    GetFramebufferAttachmentParameteriv => get_framebuffer_attachment_parameteriv,
+    GetError => get_error,
+    GetIntegerv => get_integerv,
+    GetProgramBinary => get_program_binary,
+    GetProgramiv => get_programiv,
+    GetShaderiv => get_shaderiv,
+    GetString => get_string,
+    GetUniformBlockIndex => get_uniform_block_index,
+    GetUniformLocation => get_uniform_location,
+    IsEnabled => is_enabled,
+    IsProgram => is_program,
+    LinkProgram => link_program,
+    MapBufferRange => map_buffer_range,
+    MinSampleShading => min_sample_shading,
+    PixelStorei => pixel_storei,
+    ProgramBinary => program_binary,
+    ProgramParameteri => program_parameteri,
+    ReadPixels => read_pixels,
+    RenderbufferStorage => renderbuffer_storage,
+    RenderbufferStorageMultisample => renderbuffer_storage_multisample,
+    ShaderSource => shader_source,
+    TexImage2D => tex_image_2d,
+    TexParameteri => tex_parameteri,
+    TexStorage2D => tex_storage_2d,
+    TexSubImage2D => tex_sub_image_2d,
+    UnmapBuffer => unmap_buffer,
+    Uniform1f => uniform_1f,
+    Uniform1i => uniform_1i,
+    UniformBlockBinding => uniform_block_binding,
+    UniformMatrix3fv => uniform_matrix_3fv,
+    UseProgram => use_program,
+    VertexAttribDivisor => vertex_attrib_divisor,
+    VertexAttribPointer => vertex_attrib_pointer,
+    Viewport => viewport,
+}
+
+extern "system" fn bind_buffer(target: GLenum, buffer: GLuint) {
+    record(format!("glBindBuffer({:#x}, {})", target, buffer));
+    with_state(|state| state.bound_buffers.insert(target, buffer));
+}
+
+extern "system" fn bind_buffer_base(target: GLenum, index: GLuint, buffer: GLuint) {
+    record(format!(
+        "glBindBufferBase({:#x}, {}, {})",
+        target, index, buffer
+    ));
+}
+
+extern "system" fn buffer_data(
+    target: GLenum,
+    size: GLsizeiptr,
+    data: *const c_void,
+    usage: GLenum,
+) {
+    record(format!(
+        "glBufferData({:#x}, {}, {:#x})",
+        target, size, usage
+    ));
+    let contents = if data.is_null() {
+        vec![0; size as usize]
+    } else {
+        unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }.to_vec()
+    };
+    with_state(|state| {
+        *state.bound_buffer(target) = FakeBuffer {
+            data: contents,
+            usage,
+        }
+    });
+}
+
+extern "system" fn buffer_sub_data(
+    target: GLenum,
+    offset: GLintptr,
+    size: GLsizeiptr,
+    data: *const c_void,
+) {
+    record(format!(
+        "glBufferSubData({:#x}, {}, {})",
+        target, offset, size
+    ));
+    let contents = unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) };
+    with_state(|state| {
+        let offset = offset as usize;
+        state.bound_buffer(target).data[offset..offset + contents.len()].copy_from_slice(contents);
+    });
+}
+
+extern "system" fn copy_buffer_sub_data(
+    read_target: GLenum,
+    write_target: GLenum,
+    read_offset: GLintptr,
+    write_offset: GLintptr,
+    size: GLsizeiptr,
+) {
+    record(format!(
+        "glCopyBufferSubData({:#x}, {:#x}, {}, {}, {})",
+        read_target, write_target, read_offset, write_offset, size
+    ));
+    with_state(|state| {
+        let (read_offset, write_offset) = (read_offset as usize, write_offset as usize);
+        let contents =
+            state.bound_buffer(read_target).data[read_offset..][..size as usize].to_vec();
+        state.bound_buffer(write_target).data[write_offset..][..contents.len()]
+            .copy_from_slice(&contents);
+    });
+}
+
+extern "system" fn delete_buffers(n: GLsizei, buffers: *const GLuint) {
+    let ids = unsafe { std::slice::from_raw_parts(buffers, n as usize) };
+    record(format!("glDeleteBuffers({:?})", ids));
+    with_state(|state| {
+        for id in ids {
+            state.buffers.remove(id);
+        }
+    });
+}
+
+extern "system" fn flush_mapped_buffer_range(target: GLenum, offset: GLintptr, length: GLsizeiptr) {
+    record(format!(
+        "glFlushMappedBufferRange({:#x}, {}, {})",
+        target, offset, length
+    ));
+}
+
+extern "system" fn gen_buffers(n: GLsizei, buffers: *mut GLuint) {
+    record(format!("glGenBuffers({})", n));
+    let ids = unsafe { std::slice::from_raw_parts_mut(buffers, n as usize) };
+    with_state(|state| {
+        for id in ids {
+            *id = state.next_id();
+            state.buffers.insert(*id, FakeBuffer::default());
+        }
+    });
+}
+
+extern "system" fn get_buffer_parameteriv(target: GLenum, pname: GLenum, data: *mut GLint) {
+    let value = with_state(|state| {
+        let buffer = state.bound_buffer(target);
+        match pname {
+            gl::BUFFER_SIZE => buffer.data.len() as GLint,
+            gl::BUFFER_USAGE => buffer.usage as GLint,
+            _ => 0,
+        }
+    });
+    unsafe { *data = value };
+}
+
+// Points straight into the fake store, which stays put as long as nothing reallocates it while
+// mapped, which GL forbids anyway.
+extern "system" fn map_buffer_range(
+    target: GLenum,
+    offset: GLintptr,
+    length: GLsizeiptr,
+    access: GLbitfield,
+) -> *mut c_void {
+    record(format!(
+        "glMapBufferRange({:#x}, {}, {}, {:#x})",
+        target, offset, length, access
+    ));
+    with_state(|state| {
+        let data = &mut state.bound_buffer(target).data;
+        assert!((offset + length) as usize <= data.len());
+        unsafe { data.as_mut_ptr().add(offset as usize) as *mut c_void }
+    })
+}
+
+extern "system" fn unmap_buffer(target: GLenum) -> GLboolean {
+    record(format!("glUnmapBuffer({:#x})", target));
+    gl::TRUE
+}
+
+extern "system" fn gen_vertex_arrays(n: GLsizei, arrays: *mut GLuint) {
+    record(format!("glGenVertexArrays({})", n));
+    let ids = unsafe { std::slice::from_raw_parts_mut(arrays, n as usize) };
+    with_state(|state| {
+        for id in ids {
+            *id = state.next_id();
+        }
+    });
+}
+
+extern "system" fn bind_vertex_array(array: GLuint) {
+    record(format!("glBindVertexArray({})", array));
+    with_state(|state| {
+        state
+            .integers
+            .insert(gl::VERTEX_ARRAY_BINDING, vec![array as GLint])
+    });
+}
+
+extern "system" fn delete_vertex_arrays(n: GLsizei, arrays: *const GLuint) {
+    let ids = unsafe { std::slice::from_raw_parts(arrays, n as usize) };
+    record(format!("glDeleteVertexArrays({:?})", ids));
+}
+
+extern "system" fn enable_vertex_attrib_array(index: GLuint) {
+    record(format!("glEnableVertexAttribArray({})", index));
+}
+
+extern "system" fn disable_vertex_attrib_array(index: GLuint) {
+    record(format!("glDisableVertexAttribArray({})", index));
+}
+
+extern "system" fn vertex_attrib_divisor(index: GLuint, divisor: GLuint) {
+    record(format!("glVertexAttribDivisor({}, {})", index, divisor));
+}
+
+extern "system" fn vertex_attrib_pointer(
+    index: GLuint,
+    size: GLint,
+    data_type: GLenum,
+    _normalized: GLboolean,
+    stride: GLsizei,
+    pointer: *const c_void,
+) {
+    record(format!(
+        "glVertexAttribPointer({}, {}, {:#x}, {}, {})",
+        index, size, data_type, stride, pointer as usize
+    ));
+}
+
+extern "system" fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei) {
+    record(format!("glDrawArrays({:#x}, {}, {})", mode, first, count));
+}
+
+extern "system" fn draw_arrays_instanced(
+    mode: GLenum,
+    first: GLint,
+    count: GLsizei,
+    instance_count: GLsizei,
+) {
+    record(format!(
+        "glDrawArraysInstanced({:#x}, {}, {}, {})",
+        mode, first, count, instance_count
+    ));
+}
+
+extern "system" fn draw_elements(
+    mode: GLenum,
+    count: GLsizei,
+    index_type: GLenum,
+    _indices: *const c_void,
+) {
+    record(format!(
+        "glDrawElements({:#x}, {}, {:#x})",
+        mode, count, index_type
+    ));
+}
+
+extern "system" fn draw_elements_instanced(
+    mode: GLenum,
+    count: GLsizei,
+    index_type: GLenum,
+    _indices: *const c_void,
+    instance_count: GLsizei,
+) {
+    record(format!(
+        "glDrawElementsInstanced({:#x}, {}, {:#x}, {})",
+        mode, count, index_type, instance_count
+    ));
+}
+
+extern "system" fn blend_equation_separate(color: GLenum, alpha: GLenum) {
+    record(format!(
+        "glBlendEquationSeparate({:#x}, {:#x})",
+        color, alpha
+    ));
+}
+
+extern "system" fn blend_func_separate(
+    src_color: GLenum,
+    dst_color: GLenum,
+    src_alpha: GLenum,
+    dst_alpha: GLenum,
+) {
+    record(format!(
+        "glBlendFuncSeparate({:#x}, {:#x}, {:#x}, {:#x})",
+        src_color, dst_color, src_alpha, dst_alpha
+    ));
+}
+
+extern "system" fn clear_depth(depth: GLdouble) {
+    record(format!("glClearDepth({})", depth));
+}
+
+extern "system" fn clip_control(origin: GLenum, depth: GLenum) {
+    record(format!("glClipControl({:#x}, {:#x})", origin, depth));
+}
+
+extern "system" fn depth_func(func: GLenum) {
+    record(format!("glDepthFunc({:#x})", func));
+}
+
+extern "system" fn depth_mask(flag: GLboolean) {
+    record(format!("glDepthMask({})", flag));
+}
+
+extern "system" fn enable(capability: GLenum) {
+    record(format!("glEnable({:#x})", capability));
+    with_state(|state| state.enabled.insert(capability));
+}
+
+extern "system" fn disable(capability: GLenum) {
+    record(format!("glDisable({:#x})", capability));
+    with_state(|state| state.enabled.remove(&capability));
+}
+
+extern "system" fn is_enabled(capability: GLenum) -> GLboolean {
+    if with_state(|state| state.enabled.contains(&capability)) {
+        gl::TRUE
+    } else {
+        gl::FALSE
+    }
+}
+
+extern "system" fn get_framebuffer_attachment_parameteriv(
+    _target: GLenum,
+    _attachment: GLenum,
+    pname: GLenum,
+    data: *mut GLint,
+) {
+    get_integerv(pname, data);
+}
+
+extern "system" fn min_sample_shading(value: GLfloat) {
+    record(format!("glMinSampleShading({})", value));
+}
+
+extern "system" fn gen_textures(n: GLsizei, textures: *mut GLuint) {
+    record(format!("glGenTextures({})", n));
+    let ids = unsafe { std::slice::from_raw_parts_mut(textures, n as usize) };
+    with_state(|state| {
+        for id in ids {
+            *id = state.next_id();
+        }
+    });
+}
+
+extern "system" fn active_texture(texture: GLenum) {
+    record(format!("glActiveTexture({:#x})", texture));
+}
+
+extern "system" fn bind_texture(target: GLenum, texture: GLuint) {
+    record(format!("glBindTexture({:#x}, {})", target, texture));
+}
+
+extern "system" fn delete_textures(n: GLsizei, textures: *const GLuint) {
+    let ids = unsafe { std::slice::from_raw_parts(textures, n as usize) };
+    record(format!("glDeleteTextures({:?})", ids));
+}
+
+extern "system" fn tex_parameteri(target: GLenum, pname: GLenum, param: GLint) {
+    record(format!(
+        "glTexParameteri({:#x}, {:#x}, {:#x})",
+        target, pname, param
+    ));
+}
+
+extern "system" fn tex_image_2d(
+    target: GLenum,
+    level: GLint,
+    _internal_format: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    _border: GLint,
+    _format: GLenum,
+    _pixel_type: GLenum,
+    _pixels: *const c_void,
+) {
+    record(format!(
+        "glTexImage2D({:#x}, {}, {}, {})",
+        target, level, width, height
+    ));
+}
+
+extern "system" fn tex_storage_2d(
+    target: GLenum,
+    levels: GLsizei,
+    internal_format: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+) {
+    record(format!(
+        "glTexStorage2D({:#x}, {}, {:#x}, {}, {})",
+        target, levels, internal_format, width, height
+    ));
+}
+
+extern "system" fn tex_sub_image_2d(
+    target: GLenum,
+    level: GLint,
+    _x_offset: GLint,
+    _y_offset: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    _format: GLenum,
+    _pixel_type: GLenum,
+    _pixels: *const c_void,
+) {
+    record(format!(
+        "glTexSubImage2D({:#x}, {}, {}, {})",
+        target, level, width, height
+    ));
+}
+
+extern "system" fn generate_mipmap(target: GLenum) {
+    record(format!("glGenerateMipmap({:#x})", target));
+}
+
+extern "system" fn gen_framebuffers(n: GLsizei, framebuffers: *mut GLuint) {
+    record(format!("glGenFramebuffers({})", n));
+    let ids = unsafe { std::slice::from_raw_parts_mut(framebuffers, n as usize) };
+    with_state(|state| {
+        for id in ids {
+            *id = state.next_id();
+        }
+    });
+}
+
+extern "system" fn bind_framebuffer(target: GLenum, framebuffer: GLuint) {
+    record(format!("glBindFramebuffer({:#x}, {})", target, framebuffer));
+}
+
+extern "system" fn delete_framebuffers(n: GLsizei, framebuffers: *const GLuint) {
+    let ids = unsafe { std::slice::from_raw_parts(framebuffers, n as usize) };
+    record(format!("glDeleteFramebuffers({:?})", ids));
+}
+
+// Every framebuffer is complete.
+extern "system" fn check_framebuffer_status(target: GLenum) -> GLenum {
+    record(format!("glCheckFramebufferStatus({:#x})", target));
+    gl::FRAMEBUFFER_COMPLETE
+}
+
+extern "system" fn framebuffer_texture_2d(
+    target: GLenum,
+    attachment: GLenum,
+    texture_target: GLenum,
+    texture: GLuint,
+    level: GLint,
+) {
+    record(format!(
+        "glFramebufferTexture2D({:#x}, {:#x}, {:#x}, {}, {})",
+        target, attachment, texture_target, texture, level
+    ));
+}
+
+extern "system" fn framebuffer_renderbuffer(
+    target: GLenum,
+    attachment: GLenum,
+    renderbuffer_target: GLenum,
+    renderbuffer: GLuint,
+) {
+    record(format!(
+        "glFramebufferRenderbuffer({:#x}, {:#x}, {:#x}, {})",
+        target, attachment, renderbuffer_target, renderbuffer
+    ));
+}
+
+extern "system" fn gen_renderbuffers(n: GLsizei, renderbuffers: *mut GLuint) {
+    record(format!("glGenRenderbuffers({})", n));
+    let ids = unsafe { std::slice::from_raw_parts_mut(renderbuffers, n as usize) };
+    with_state(|state| {
+        for id in ids {
+            *id = state.next_id();
+        }
+    });
+}
+
+extern "system" fn bind_renderbuffer(target: GLenum, renderbuffer: GLuint) {
+    record(format!(
+        "glBindRenderbuffer({:#x}, {})",
+        target, renderbuffer
+    ));
+}
+
+extern "system" fn renderbuffer_storage(
+    target: GLenum,
+    internal_format: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+) {
+    record(format!(
+        "glRenderbufferStorage({:#x}, {:#x}, {}, {})",
+        target, internal_format, width, height
+    ));
+}
+
+extern "system" fn renderbuffer_storage_multisample(
+    target: GLenum,
+    samples: GLsizei,
+    internal_format: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+) {
+    record(format!(
+        "glRenderbufferStorageMultisample({:#x}, {}, {:#x}, {}, {})",
+        target, samples, internal_format, width, height
+    ));
+}
+
+extern "system" fn delete_renderbuffers(n: GLsizei, renderbuffers: *const GLuint) {
+    let ids = unsafe { std::slice::from_raw_parts(renderbuffers, n as usize) };
+    record(format!("glDeleteRenderbuffers({:?})", ids));
+}
+
+extern "system" fn blit_framebuffer(
+    src_x0: GLint,
+    src_y0: GLint,
+    src_x1: GLint,
+    src_y1: GLint,
+    dst_x0: GLint,
+    dst_y0: GLint,
+    dst_x1: GLint,
+    dst_y1: GLint,
+    mask: GLbitfield,
+    filter: GLenum,
+) {
+    record(format!(
+        "glBlitFramebuffer({}, {}, {}, {} -> {}, {}, {}, {}, {:#x}, {:#x})",
+        src_x0, src_y0, src_x1, src_y1, dst_x0, dst_y0, dst_x1, dst_y1, mask, filter
+    ));
+}
+
+extern "system" fn viewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    record(format!("glViewport({}, {}, {}, {})", x, y, width, height));
+}
+
+extern "system" fn pixel_storei(pname: GLenum, param: GLint) {
+    record(format!("glPixelStorei({:#x}, {})", pname, param));
+}
+
+// Fills the rectangle with a gray gradient from 0 in the first column to 1 in the last, the
+// same in every channel and row. Only tightly packed RGB bytes or floats are supported.
+extern "system" fn read_pixels(
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    format: GLenum,
+    pixel_type: GLenum,
+    pixels: *mut c_void,
+) {
+    record(format!(
+        "glReadPixels({}, {}, {}, {}, {:#x}, {:#x})",
+        x, y, width, height, format, pixel_type
+    ));
+    assert_eq!(format, gl::RGB);
+
+    let (width, height) = (width as usize, height as usize);
+    let values = (0..width * height * 3).map(|i| (i / 3 % width) as f32 / (width - 1) as f32);
+    unsafe {
+        match pixel_type {
+            gl::UNSIGNED_BYTE => {
+                let pixels = std::slice::from_raw_parts_mut(pixels as *mut u8, width * height * 3);
+                for (pixel, value) in pixels.iter_mut().zip(values) {
+                    *pixel = (value * 255.0).round() as u8;
+                }
+            }
+            gl::FLOAT => {
+                let pixels = std::slice::from_raw_parts_mut(pixels as *mut f32, width * height * 3);
+                for (pixel, value) in pixels.iter_mut().zip(values) {
+                    *pixel = value;
+                }
+            }
+            _ => panic!("unsupported pixel type {:#x}", pixel_type),
+        }
+    }
+}
+
+extern "system" fn get_error() -> GLenum {
+    gl::NO_ERROR
+}
+
+extern "system" fn get_integerv(pname: GLenum, data: *mut GLint) {
+    let values = with_state(|state| state.integers.get(&pname).cloned());
+    let values = values.unwrap_or_else(|| vec![0]);
+    unsafe { std::ptr::copy_nonoverlapping(values.as_ptr(), data, values.len()) };
+}
+
+extern "system" fn delete_program(program: GLuint) {
+    record(format!("glDeleteProgram({})", program));
+}
+
+extern "system" fn is_program(_program: GLuint) -> GLboolean {
+    gl::FALSE
+}
+
+// No vendor, renderer or version strings.
+extern "system" fn get_string(_name: GLenum) -> *const GLubyte {
+    std::ptr::null()
+}
+
+extern "system" fn create_shader(shader_type: GLenum) -> GLuint {
+    record(format!("glCreateShader({:#x})", shader_type));
+    with_state(|state| state.next_id())
+}
+
+extern "system" fn shader_source(
+    shader: GLuint,
+    _count: GLsizei,
+    _string: *const *const GLchar,
+    _length: *const GLint,
+) {
+    record(format!("glShaderSource({})", shader));
+}
+
+extern "system" fn compile_shader(shader: GLuint) {
+    record(format!("glCompileShader({})", shader));
+}
+
+// Every shader compiles.
+extern "system" fn get_shaderiv(_shader: GLuint, pname: GLenum, params: *mut GLint) {
+    let value = match pname {
+        gl::COMPILE_STATUS => 1,
+        _ => 0,
+    };
+    unsafe { *params = value };
+}
+
+extern "system" fn delete_shader(shader: GLuint) {
+    record(format!("glDeleteShader({})", shader));
+}
+
+extern "system" fn create_program() -> GLuint {
+    record("glCreateProgram()".to_string());
+    with_state(|state| state.next_id())
+}
+
+extern "system" fn attach_shader(program: GLuint, shader: GLuint) {
+    record(format!("glAttachShader({}, {})", program, shader));
+}
+
+extern "system" fn program_parameteri(program: GLuint, pname: GLenum, value: GLint) {
+    record(format!(
+        "glProgramParameteri({}, {:#x}, {})",
+        program, pname, value
+    ));
+}
+
+// Every program links.
+extern "system" fn link_program(program: GLuint) {
+    record(format!("glLinkProgram({})", program));
+    with_state(|state| state.linked_programs.insert(program));
+}
+
+extern "system" fn get_programiv(program: GLuint, pname: GLenum, params: *mut GLint) {
+    let linked = with_state(|state| state.linked_programs.contains(&program));
+    let value = match pname {
+        gl::LINK_STATUS => linked as GLint,
+        gl::PROGRAM_BINARY_LENGTH if linked => PROGRAM_BINARY.len() as GLint,
+        _ => 0,
+    };
+    unsafe { *params = value };
+}
+
+extern "system" fn get_program_binary(
+    program: GLuint,
+    buffer_size: GLsizei,
+    length: *mut GLsizei,
+    binary_format: *mut GLenum,
+    binary: *mut c_void,
+) {
+    record(format!("glGetProgramBinary({})", program));
+    let size = PROGRAM_BINARY.len().min(buffer_size as usize);
+    unsafe {
+        std::ptr::copy_nonoverlapping(PROGRAM_BINARY.as_ptr(), binary as *mut u8, size);
+        *length = size as GLsizei;
+        *binary_format = PROGRAM_BINARY_FORMAT;
+    }
+}
+
+// Links the program if given `PROGRAM_BINARY` in `PROGRAM_BINARY_FORMAT`, anything else is
+// rejected like a binary from another driver.
+extern "system" fn program_binary(
+    program: GLuint,
+    binary_format: GLenum,
+    binary: *const c_void,
+    length: GLsizei,
+) {
+    record(format!(
+        "glProgramBinary({}, {:#x}, {})",
+        program, binary_format, length
+    ));
+    let binary = unsafe { std::slice::from_raw_parts(binary as *const u8, length as usize) };
+    let accepted = binary_format == PROGRAM_BINARY_FORMAT && binary == PROGRAM_BINARY;
+    with_state(|state| {
+        if accepted {
+            state.linked_programs.insert(program);
+        } else {
+            state.linked_programs.remove(&program);
+        }
+    });
+}
+
+// Every uniform exists, at location 0.
+extern "system" fn get_uniform_location(program: GLuint, name: *const GLchar) -> GLint {
+    let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy();
+    record(format!("glGetUniformLocation({}, {})", program, name));
+    0
+}
+
+// Every block exists, at index 0.
+extern "system" fn get_uniform_block_index(program: GLuint, name: *const GLchar) -> GLuint {
+    let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy();
+    record(format!("glGetUniformBlockIndex({}, {})", program, name));
+    0
+}
+
+extern "system" fn uniform_block_binding(program: GLuint, index: GLuint, binding: GLuint) {
+    record(format!(
+        "glUniformBlockBinding({}, {}, {})",
+        program, index, binding
+    ));
+}
+
+extern "system" fn uniform_matrix_3fv(
+    location: GLint,
+    count: GLsizei,
+    transpose: GLboolean,
+    value: *const GLfloat,
+) {
+    let values = unsafe { std::slice::from_raw_parts(value, 9 * count as usize) };
+    record(format!(
+        "glUniformMatrix3fv({}, {}, {}, {:?})",
+        location, count, transpose, values
+    ));
+}
+
+extern "system" fn uniform_1f(location: GLint, value: GLfloat) {
+    record(format!("glUniform1f({}, {})", location, value));
+}
+
+extern "system" fn uniform_1i(location: GLint, value: GLint) {
+    record(format!("glUniform1i({}, {})", location, value));
+}
+
+extern "system" fn front_face(mode: GLenum) {
+    record(format!("glFrontFace({:#x})", mode));
+}
+
+extern "system" fn use_program(program: GLuint) {
+    record(format!("glUseProgram({})", program));
+    with_state(|state| {
+        state
+            .integers
+            .insert(gl::CURRENT_PROGRAM, vec![program as GLint])
+    });
+}
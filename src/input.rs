@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use glfw::{Action, Key, MouseButton, WindowEvent};
+
+// Keyboard and mouse click state built from `WindowEvent::Key` and `WindowEvent::MouseButton`
+// events, so scenes can ask which keys are held each frame instead of reacting to individual
+// events. Key repeats are ignored, their delay and rate are up to the OS.
+#[derive(Default)]
+pub struct Input {
+    down: HashSet<Key>,
+    // Keys that went down since the last `end_frame`.
+    pressed: HashSet<Key>,
+    // Mouse buttons that went down since the last `end_frame`.
+    buttons_pressed: HashSet<MouseButton>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::Key(key, _, Action::Press, _) => {
+                self.down.insert(key);
+                self.pressed.insert(key);
+            }
+            WindowEvent::Key(key, _, Action::Release, _) => {
+                self.down.remove(&key);
+            }
+            WindowEvent::MouseButton(button, Action::Press, _) => {
+                self.buttons_pressed.insert(button);
+            }
+            _ => {}
+        }
+    }
+
+    // Forgets this frame's presses, `just_pressed` only reports a key for one frame.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.buttons_pressed.clear();
+    }
+}
+
+impl Input {
+    pub fn is_down(&self, key: Key) -> bool {
+        self.down.contains(&key)
+    }
+
+    // True for the frame after the key went down, even if it was released again since.
+    pub fn just_pressed(&self, key: Key) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn button_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+}
@@ -0,0 +1,82 @@
+// Every GL wrapper is unsafe for the same reason: it needs a context current on the calling
+// thread with the functions loaded (see `debug::assert_context_current`). That isn't repeated in
+// a `# Safety` section on each of them.
+#![allow(clippy::missing_safety_doc)]
+
+#[macro_use]
+mod trace;
+#[macro_use]
+pub mod debug;
+#[macro_use]
+pub mod profiling;
+
+pub mod app;
+pub mod buffers;
+pub mod camera;
+pub mod camera_path;
+pub mod color;
+pub mod error;
+pub mod frame_latency;
+pub mod frame_stats;
+pub mod framebuffer;
+pub mod fxaa;
+#[cfg(test)]
+mod gl_spy;
+pub mod input;
+pub mod math;
+pub mod mesh;
+pub mod readback;
+pub mod render_state;
+pub mod renderer;
+pub mod shaders;
+pub mod std140;
+pub mod texture;
+
+// The wrappers are tied to the context's thread (see `buffers::Buffer`), so moving one to another
+// thread must not compile. Each case is its own doctest, a block fails to compile as soon as any
+// line in it does.
+/// ```compile_fail
+/// fn send(buffer: opengl_rust::buffers::Buffer<u8>) {
+///     std::thread::spawn(move || drop(buffer));
+/// }
+/// ```
+///
+/// ```compile_fail
+/// fn send(vertex_array: opengl_rust::buffers::VertexArray) {
+///     std::thread::spawn(move || drop(vertex_array));
+/// }
+/// ```
+///
+/// ```compile_fail
+/// fn send(shader: opengl_rust::shaders::Shader) {
+///     std::thread::spawn(move || drop(shader));
+/// }
+/// ```
+///
+/// ```compile_fail
+/// fn send(program: opengl_rust::shaders::ShaderProgram) {
+///     std::thread::spawn(move || drop(program));
+/// }
+/// ```
+///
+/// ```compile_fail
+/// fn send(texture: opengl_rust::texture::Texture) {
+///     std::thread::spawn(move || drop(texture));
+/// }
+/// ```
+///
+/// ```compile_fail
+/// fn send(framebuffer: opengl_rust::framebuffer::Framebuffer) {
+///     std::thread::spawn(move || drop(framebuffer));
+/// }
+/// ```
+///
+/// The same function with a `Send` type does compile, so the cases above fail for the right reason.
+///
+/// ```
+/// fn send(id: u32) {
+///     std::thread::spawn(move || drop(id));
+/// }
+/// ```
+#[cfg(doctest)]
+pub struct WrappersAreNotSend;
@@ -1,239 +1,870 @@
-mod shaders;
-use crate::shaders::*;
-
-use gl;
-use gl::types::*;
-
-use glfw;
-use glfw::{Action, Context, Key};
-use std::{
-    ffi::{c_void, CString},
-    fs::File,
-    io::Read,
-    mem::{size_of, size_of_val},
-};
-
-fn main() {
-    // std::env::set_var("RUST_BACKTRACE", "1");
-
-    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
-
-    glfw.window_hint(glfw::WindowHint::ContextVersion(4, 2));
-    glfw.window_hint(glfw::WindowHint::OpenGlProfile(
-        glfw::OpenGlProfileHint::Core,
-    ));
-
-    let (mut window, events) = glfw
-        .create_window(800, 600, "OpenGL in Rust", glfw::WindowMode::Windowed)
-        .expect("Failed to create GLFW window.");
-
-    window.set_key_polling(true);
-    window.set_resizable(false);
-    window.make_current();
-
-    // let workdir = std::env::current_dir().unwrap();
-    // println!("{}", workdir.display());
-
-    gl::load_with(|s| window.get_proc_address(s));
-
-    // SHADERS
-
-    let mut vertex_src = File::open("shaders/basic_vertex.vert").expect("Erro ao abrir o arquivo");
-    let mut fragment_src =
-        File::open("shaders/basic_fragment.frag").expect("Erro ao abrir o arquivo");
-
-    let mut vertex_shader_src = String::new();
-    let mut fragment_shader_src = String::new();
+use opengl_rust::app::{App, Scene, WindowConfig};
+use opengl_rust::buffers::{Buffer, BufferUsage};
+use opengl_rust::camera::{Camera, CameraMovement, FAR_PLANE, NEAR_PLANE};
+use opengl_rust::camera_path::{CameraKeyframe, CameraPath};
+use opengl_rust::color::Color;
+use opengl_rust::error::Error;
+use opengl_rust::framebuffer::Framebuffer;
+use opengl_rust::fxaa::{Fxaa, FxaaQuality};
+use opengl_rust::math::Transform;
+use opengl_rust::mesh::{Mesh, MeshDescriptor, ObjVertex};
+use opengl_rust::readback::{linearize_depth, read_depth_from};
+use opengl_rust::render_state::*;
+use opengl_rust::renderer::{self, DrawMode};
+use opengl_rust::shaders::{Shader, ShaderProgram, FRAME_BLOCK, FRAME_UBO_BINDING};
+use opengl_rust::std140::Std140Builder;
+use opengl_rust::texture::Texture;
+use opengl_rust::{debug, gl_check};
+
+use glam::{Quat, Vec3};
+use glfw::{CursorMode, Key, MouseButton, WindowEvent};
+use std::mem::size_of;
+
+const VERTEX_SHADER_PATH: &str = "shaders/basic_vertex.vert";
+const FRAGMENT_SHADER_PATH: &str = "shaders/basic_fragment.frag";
+
+// Linked binaries of the programs built below are kept here, so later runs skip compiling them.
+const SHADER_CACHE_DIR: &str = "shader_cache";
+
+// Expands points into camera-facing quads in a geometry shader.
+const BILLBOARD_VERTEX_SHADER_PATH: &str = "shaders/billboard.vert";
+const BILLBOARD_GEOMETRY_SHADER_PATH: &str = "shaders/billboard.geom";
+const BILLBOARD_FRAGMENT_SHADER_PATH: &str = "shaders/billboard.frag";
+
+// A quad behind the grid, tessellated and rippled on the GPU.
+const TESSELLATION_VERTEX_SHADER_PATH: &str = "shaders/tessellation.vert";
+const TESSELLATION_CONTROL_SHADER_PATH: &str = "shaders/tessellation.tesc";
+const TESSELLATION_EVALUATION_SHADER_PATH: &str = "shaders/tessellation.tese";
+const TESSELLATION_FRAGMENT_SHADER_PATH: &str = "shaders/tessellation.frag";
+
+// A circle in the middle of the window, behind everything else, that stays round when the
+// window is resized. Drawn on the quad stretched over the whole screen.
+const SCREEN_QUAD_VERTEX_SHADER_PATH: &str = "shaders/screen_quad.vert";
+const CIRCLE_FRAGMENT_SHADER_PATH: &str = "shaders/circle_fragment.frag";
+
+// Antialiases the finished frame on its way to the window, on the same screen quad.
+const FXAA_FRAGMENT_SHADER_PATH: &str = "shaders/fxaa.frag";
+const FXAA_QUALITY: FxaaQuality = FxaaQuality::High;
+
+// Squares the indices 0..COMPUTE_VALUE_COUNT in a shader storage buffer at startup, to check that
+// compute results make it back to the CPU. Needs GL 4.3, which is more than the demo asks for,
+// so it is skipped on older contexts.
+const COMPUTE_SHADER_PATH: &str = "shaders/square.comp";
+const COMPUTE_VALUE_COUNT: usize = 256;
+const COMPUTE_GROUP_SIZE: usize = 64;
+
+// Loaded with the OBJ loader. It has no vertex colors, its normals land in the color attribute
+// instead, which shades each face by the direction it faces.
+const MODEL_PATH: &str = "models/pyramid.obj";
+
+// Every program that draws the scene reads the camera matrices from one uniform buffer, the
+// `Frame` block in shaders/camera.glsl, attached to FRAME_UBO_BINDING. Two mat4s.
+const CAMERA_BLOCK_SIZE: usize = 128;
+
+// The scene is drawn into an offscreen framebuffer the size of the window, then copied to the
+// window, through the FXAA pass while it is on. It isn't multisampled, so its color can be
+// sampled and its depth buffer read back directly.
+
+// Billboards circling above the grid.
+const BILLBOARD_COUNT: usize = 12;
+const BILLBOARD_RING_RADIUS: f32 = 1.2;
+
+// The quad is drawn once per cell of a GRID_SIZE x GRID_SIZE grid, with GRID_SPACING between
+// the centers of neighbouring cells.
+const GRID_SIZE: usize = 4;
+const GRID_SPACING: f32 = 0.5;
+
+// One quad of the grid bobs up and down. Its offset is rewritten in place every frame by mapping
+// just its slice of the instance buffer, the rest of the batch is left alone.
+const BOBBING_QUAD: usize = 5;
+const BOB_HEIGHT: f32 = 0.1;
+// Radians per second.
+const BOB_SPEED: f32 = 3.0;
+
+// Keys that move the camera while held.
+const MOVEMENT_KEYS: [(Key, CameraMovement); 10] = [
+    (Key::W, CameraMovement::Forward),
+    (Key::S, CameraMovement::Backward),
+    (Key::A, CameraMovement::Left),
+    (Key::D, CameraMovement::Right),
+    (Key::Space, CameraMovement::Up),
+    (Key::LeftShift, CameraMovement::Down),
+    (Key::Right, CameraMovement::Right),
+    (Key::Left, CameraMovement::Left),
+    (Key::Up, CameraMovement::Up),
+    (Key::Down, CameraMovement::Down),
+];
+
+// Toggles between mouse look, with the cursor hidden and captured, and a free cursor.
+const CURSOR_CAPTURE_KEY: Key = Key::Tab;
+
+// Saves the frame to SCREENSHOT_PATH, overwriting the previous screenshot.
+const SCREENSHOT_KEY: Key = Key::F2;
+const SCREENSHOT_PATH: &str = "screenshot.png";
+
+// Toggles FXAA, to compare the edges with and without it.
+const FXAA_KEY: Key = Key::F;
+
+// Starts and pauses a looping fly-through around the scene, as (time, position, target). The
+// first and last keyframes match so the loop has no seam.
+const CAMERA_PATH_KEY: Key = Key::P;
+const CAMERA_PATH: [(f32, [f32; 3], [f32; 3]); 5] = [
+    (0.0, [0.0, 0.0, 3.0], [0.0, 0.0, 0.0]),
+    (4.0, [2.5, 1.0, 1.5], [0.0, 0.0, 0.5]),
+    (8.0, [0.5, 2.0, -2.5], [0.0, 0.0, 0.0]),
+    (12.0, [-2.5, 0.5, 1.0], [0.0, 0.0, 0.5]),
+    (16.0, [0.0, 0.0, 3.0], [0.0, 0.0, 0.0]),
+];
+
+// Prints the distance to whatever is under the cursor, or under the middle of the window while
+// the cursor is captured.
+const PICK_BUTTON: MouseButton = MouseButton::Button1;
+
+// Radians per second.
+const CUBE_SPIN_SPEED: f32 = 1.0;
+
+// Two ribbons drawn as triangle strips from one index buffer in one draw call, split by this index
+// with primitive restart.
+const RESTART_INDEX: u32 = u32::MAX;
+const RIBBON_SEGMENTS: usize = 6;
+
+// The same diamond outline twice, aliased on the left and with line smoothing on the right.
+const OUTLINE_POSITIONS: [[f32; 3]; 2] = [[0.45, 0.75, 0.3], [0.75, 0.75, 0.3]];
+const OUTLINE_RADIUS: f32 = 0.12;
+
+// Drawn with blending after the opaque geometry, furthest first, as (position, tint).
+const TRANSLUCENT_QUADS: [([f32; 3], Color); 2] = [
+    ([-0.35, -0.2, 1.2], Color::rgba(1.0, 0.3, 0.3, 0.5)),
+    ([-0.15, -0.35, 1.4], Color::rgba(0.3, 0.3, 1.0, 0.5)),
+];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct QuadVertex {
+    position: [f32; 3],
+    color: Color,
+    tex_coord: [f32; 2],
+}
 
-    vertex_src
-        .read_to_string(&mut vertex_shader_src)
-        .expect("Error parsing vertex shader");
-    fragment_src
-        .read_to_string(&mut fragment_shader_src)
-        .expect("Error parsing fragment shader");
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PointVertex {
+    position: [f32; 3],
+    color: Color,
+}
 
-    let shader_program = unsafe {
-        let vertex_shader = Shader::new(&vertex_shader_src, gl::VERTEX_SHADER)
-            .expect("Failed to create Vertex Shader");
-        let fragment_shader = Shader::new(&fragment_shader_src, gl::FRAGMENT_SHADER)
-            .expect("Failed to create Fragment Shader");
-        let shader_program = ShaderProgram::new(&[vertex_shader, fragment_shader])
-            .expect("Failed to create Shader Program");
-        shader_program
-    };
+// A textured cube one unit across, centered on the origin, wound clockwise like the quad.
+fn cube() -> (Vec<QuadVertex>, Vec<u32>) {
+    // Each face as (normal, u axis, v axis), with u x v pointing along the normal.
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    for (normal, u, v) in faces {
+        let first = vertices.len() as u32;
+
+        // Counter-clockwise seen from outside, the index order below flips it to clockwise.
+        for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = [0, 1, 2].map(|i| (normal[i] + u[i] * su + v[i] * sv) * 0.5);
+            vertices.push(QuadVertex {
+                position,
+                color: Color::WHITE,
+                tex_coord: [(su + 1.0) / 2.0, (sv + 1.0) / 2.0],
+            });
+        }
 
-    struct Buffer {
-        id: u32,
-        buffer_type: GLenum,
+        indices.extend([0, 2, 1, 0, 3, 2].map(|i| first + i));
     }
 
-    impl Buffer {
-        unsafe fn new(buffer_type: GLenum) -> Self {
-            let mut buffer = Self { id: 0, buffer_type };
+    (vertices, indices)
+}
 
-            gl::GenBuffers(1, &mut buffer.id);
+// Two wavy ribbons below the grid as one triangle strip each, the second restarting after
+// RESTART_INDEX. Each strip zigzags bottom, top, bottom, ..., which winds its first triangle
+// clockwise like the rest of the scene.
+fn ribbons() -> (Vec<QuadVertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
 
-            return buffer;
+    for (ribbon, y) in [-0.7, -0.9].into_iter().enumerate() {
+        if ribbon > 0 {
+            indices.push(RESTART_INDEX);
         }
-    }
 
-    impl Buffer {
-        unsafe fn bind(&self) {
-            gl::BindBuffer(self.buffer_type, self.id);
+        for i in 0..=RIBBON_SEGMENTS {
+            let u = i as f32 / RIBBON_SEGMENTS as f32;
+            let x = -0.9 + u * 0.6;
+            let wave = (u * std::f32::consts::TAU).sin() * 0.03;
+            for (v, height) in [(0.0, 0.0), (1.0, 0.08)] {
+                indices.push(vertices.len() as u32);
+                vertices.push(QuadVertex {
+                    position: [x, y + wave + height, 0.3],
+                    color: Color::WHITE,
+                    tex_coord: [u, v],
+                });
+            }
         }
     }
 
-    impl Buffer {
-        unsafe fn set_data<D>(&self, data: &[D], usage: GLuint) {
-            self.bind();
-            let (_, data_bytes, _) = data.align_to::<f32>();
+    (vertices, indices)
+}
 
-            gl::BufferData(
-                self.buffer_type,
-                size_of_val(data_bytes) as isize,
-                data.as_ptr() as *const c_void,
-                usage,
-            );
-        }
+unsafe fn check_compute_round_trip(app: &mut App) -> Result<(), Error> {
+    let version = app.window.get_context_version();
+    if (version.major, version.minor) < (4, 3) {
+        println!("Compute shaders need GL 4.3, skipping the round trip check");
+        return Ok(());
     }
 
-    impl Drop for Buffer {
-        fn drop(&mut self) {
-            unsafe { gl::DeleteBuffers(1, [self.id].as_mut_ptr()) }
+    let shader = Shader::from_file(COMPUTE_SHADER_PATH, gl::COMPUTE_SHADER)?;
+    let program = ShaderProgram::new(&[shader])?;
+    let values: Buffer<f32> = Buffer::new_shader_storage()?;
+    values.set_data(&[0.0; COMPUTE_VALUE_COUNT], BufferUsage::DynamicDraw);
+    values.bind_base(0);
+
+    app.render_state.use_program(program.id);
+    renderer::dispatch_compute((COMPUTE_VALUE_COUNT / COMPUTE_GROUP_SIZE) as u32, 1, 1);
+    gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+    gl_check!("compute dispatch");
+
+    match values.read_back() {
+        Some(squares)
+            if squares
+                .iter()
+                .enumerate()
+                .all(|(i, &square)| square == (i * i) as f32) =>
+        {
+            println!(
+                "Compute round trip: {} values squared on the GPU",
+                squares.len()
+            )
         }
+        Some(_) => eprintln!("Compute round trip: wrong values read back"),
+        None => eprintln!("Compute round trip: failed to map the storage buffer"),
     }
 
-    type Vertex = [f32; 3];
-    // let vertex_positions: [Vertex; 3] = [[-0.5, -0.5, 0.0], [0.5, -0.5, 0.0], [0.0, 0.5, 0.0]];
-    let vertex_colors: [Vertex; 3] = [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]];
+    // The program id may be handed out again once it is deleted.
+    drop(program);
+    app.render_state.invalidate_bindings();
 
-    // let index = [0, 1, 2];
+    Ok(())
+}
 
-    let vertices: [Vertex; 4] = [
-        [0.5, 0.5, 0.0],   // top right
-        [0.5, -0.5, 0.0],  // bottom right
-        [-0.5, -0.5, 0.0], // bottom left
-        [-0.5, 0.5, 0.0],  // top left
-    ];
-    let indices = [
-        // note that we start from 0!
-        0, 1, 3, // first triangle
-        1, 2, 3, // second triangle
-    ];
+// The `Frame` block's contents, laid out for std140.
+fn camera_block(projection: [[f32; 4]; 4], view: [[f32; 4]; 4]) -> Vec<u8> {
+    let mut block = Std140Builder::new();
+    block.push_mat4(projection);
+    block.push_mat4(view);
 
-    unsafe {
-        let mut vertex_array: u32 = 0;
-        let index_array = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
-        let vertex_buffer = Buffer::new(gl::ARRAY_BUFFER);
-        let color_buffer = Buffer::new(gl::ARRAY_BUFFER);
-
-        gl::GenVertexArrays(1, &mut vertex_array);
-        gl::BindVertexArray(vertex_array);
-
-        vertex_buffer.bind();
-        vertex_buffer.set_data(&vertices, gl::STATIC_DRAW);
-
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            size_of::<Vertex>() as i32,
-            0 as *const c_void,
-        );
-        gl::EnableVertexAttribArray(0);
-
-        color_buffer.bind();
-        color_buffer.set_data(&vertex_colors, gl::STATIC_DRAW);
-
-        gl::VertexAttribPointer(
-            1,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            size_of::<Vertex>() as i32,
-            0 as *const c_void,
-        );
-        gl::EnableVertexAttribArray(1);
+    block.build()
+}
 
-        index_array.bind();
-        index_array.set_data(&indices, gl::STATIC_DRAW);
+struct DemoScene {
+    offscreen: Framebuffer,
+    camera: Camera,
+    camera_path: CameraPath,
+    camera_ubo: Buffer<u8>,
+    // The main program whose `Frame` block was last bound. A hot reload replaces the program,
+    // and block bindings belong to the program object.
+    camera_block_program: u32,
+    quad: Mesh<QuadVertex>,
+    instance_buffer: Buffer<[f32; 2]>,
+    bobbing_quad_origin: [f32; 2],
+    cube: Mesh<QuadVertex>,
+    cube_transform: Transform,
+    ribbons: Mesh<QuadVertex>,
+    outline: Mesh<QuadVertex>,
+    pyramid: Mesh<ObjVertex>,
+    pyramid_transform: Transform,
+    // The quad without instance offsets.
+    sprite: Mesh<QuadVertex>,
+    circle_program: ShaderProgram,
+    fxaa: Fxaa,
+    fxaa_program: ShaderProgram,
+    billboards: Mesh<PointVertex>,
+    billboard_program: ShaderProgram,
+    // One patch of four control points.
+    ripple: Mesh<[f32; 3]>,
+    ripple_program: ShaderProgram,
+    texture: Texture,
+    cursor_captured: bool,
+    // Cursor position from the previous `CursorPos` event, None until the first one after the
+    // cursor mode changes. GLFW reports absolute positions, and the first delta would otherwise
+    // be measured from wherever the cursor happened to be.
+    last_cursor: Option<(f64, f64)>,
+}
 
-        // let uniform_name: Vec<u8> = Vec::from("xPosition");
-        let mut x_value = 0.0;
-        let mut y_value = 0.0;
+impl DemoScene {
+    unsafe fn new(app: &mut App) -> Result<Self, Error> {
+        // let vertex_positions: [Vertex; 3] = [[-0.5, -0.5, 0.0], [0.5, -0.5, 0.0], [0.0, 0.5, 0.0]];
+        // let index = [0, 1, 2];
+
+        let vertices = [
+            // top right
+            QuadVertex {
+                position: [0.2, 0.2, 0.0],
+                color: Color::rgb(0.0, 0.0, 1.0),
+                tex_coord: [1.0, 1.0],
+            },
+            // bottom right
+            QuadVertex {
+                position: [0.2, -0.2, 0.0],
+                color: Color::rgb(0.0, 1.0, 0.0),
+                tex_coord: [1.0, 0.0],
+            },
+            // bottom left
+            QuadVertex {
+                position: [-0.2, -0.2, 0.0],
+                color: Color::rgb(1.0, 0.0, 0.0),
+                tex_coord: [0.0, 0.0],
+            },
+            // top left
+            QuadVertex {
+                position: [-0.2, 0.2, 0.0],
+                color: Color::WHITE,
+                tex_coord: [0.0, 1.0],
+            },
+        ];
+        let indices: [u32; 6] = [
+            // note that we start from 0!
+            0, 1, 3, // first triangle
+            1, 2, 3, // second triangle
+        ];
+        // Both triangles run top right -> bottom right -> top left (and bottom right -> bottom
+        // left -> top left), which is clockwise on screen. Meshes are counter-clockwise unless set
+        // otherwise, so with back-face culling on the quad would disappear. The rest of the
+        // hand-built geometry below, and the billboard and ripple shaders, wind the same way.
+
+        // Per-instance offsets of the quad, centered on the origin.
+        let grid_start = -GRID_SPACING * (GRID_SIZE - 1) as f32 / 2.0;
+        let instance_offsets: Vec<[f32; 2]> = (0..GRID_SIZE * GRID_SIZE)
+            .map(|i| {
+                [
+                    grid_start + (i % GRID_SIZE) as f32 * GRID_SPACING,
+                    grid_start + (i / GRID_SIZE) as f32 * GRID_SPACING,
+                ]
+            })
+            .collect();
+
+        let quad_descriptor = MeshDescriptor {
+            vertices: &vertices,
+            indices: Some(&indices),
+            layout: &[(0, 3, gl::FLOAT), (1, 4, gl::FLOAT), (2, 2, gl::FLOAT)],
+            usage: BufferUsage::StaticDraw,
+        };
+        let mut quad = Mesh::from_descriptor(&quad_descriptor)?;
+        quad.set_winding(Winding::Clockwise);
+        let mut sprite = Mesh::from_descriptor(&quad_descriptor)?;
+        sprite.set_winding(Winding::Clockwise);
+
+        // `set_data` leaves the instance buffer bound to GL_ARRAY_BUFFER for `set_layout`.
+        let instance_buffer = Buffer::new(gl::ARRAY_BUFFER)?;
+        instance_buffer.set_data(&instance_offsets, BufferUsage::DynamicDraw);
+        let vertex_array = quad.vertex_array();
+        vertex_array.set_layout(3, 2, gl::FLOAT, gl::FALSE, size_of::<[f32; 2]>() as i32)?;
+        vertex_array.set_attribute_divisor(3, 1);
+        gl_check!("quad grid buffer setup");
+
+        // The cube has no instance offsets, attribute 3 keeps its default of (0, 0, 0, 1).
+        let (cube_vertices, cube_indices) = cube();
+        let mut cube = Mesh::from_descriptor(&MeshDescriptor {
+            vertices: &cube_vertices,
+            indices: Some(&cube_indices),
+            ..quad_descriptor
+        })?;
+        cube.set_winding(Winding::Clockwise);
+        let cube_transform = Transform {
+            position: Vec3::new(0.0, 0.0, 0.75),
+            scale: Vec3::splat(0.3),
+            ..Transform::new()
+        };
+
+        let (ribbon_vertices, ribbon_indices) = ribbons();
+        let mut ribbons = Mesh::from_descriptor(&MeshDescriptor {
+            vertices: &ribbon_vertices,
+            indices: Some(&ribbon_indices),
+            ..quad_descriptor
+        })?;
+        ribbons.set_mode(DrawMode::TriangleStrip);
+        ribbons.set_winding(Winding::Clockwise);
+
+        let outline_vertices: Vec<QuadVertex> = (0..4)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::FRAC_PI_2;
+                QuadVertex {
+                    position: [
+                        angle.cos() * OUTLINE_RADIUS,
+                        angle.sin() * OUTLINE_RADIUS,
+                        0.0,
+                    ],
+                    color: Color::rgb(1.0, 0.9, 0.2),
+                    tex_coord: [0.5, 0.5],
+                }
+            })
+            .collect();
+        let mut outline = Mesh::from_descriptor(&MeshDescriptor {
+            vertices: &outline_vertices,
+            indices: None,
+            ..quad_descriptor
+        })?;
+        outline.set_mode(DrawMode::LineLoop);
+
+        let circle_program =
+            ShaderProgram::from_files(SCREEN_QUAD_VERTEX_SHADER_PATH, CIRCLE_FRAGMENT_SHADER_PATH)?;
+        let fxaa_program =
+            ShaderProgram::from_files(SCREEN_QUAD_VERTEX_SHADER_PATH, FXAA_FRAGMENT_SHADER_PATH)?;
+
+        let pyramid = Mesh::from_obj(MODEL_PATH)?;
+        let pyramid_transform = Transform {
+            position: Vec3::new(0.8, -0.4, 0.5),
+            scale: Vec3::splat(0.4),
+            ..Transform::new()
+        };
+
+        let billboard_points: Vec<PointVertex> = (0..BILLBOARD_COUNT)
+            .map(|i| {
+                let angle = i as f32 / BILLBOARD_COUNT as f32 * std::f32::consts::TAU;
+                PointVertex {
+                    position: [
+                        angle.cos() * BILLBOARD_RING_RADIUS,
+                        0.9,
+                        angle.sin() * BILLBOARD_RING_RADIUS,
+                    ],
+                    color: Color::rgb(1.0, 0.5 + 0.5 * angle.sin(), 0.2),
+                }
+            })
+            .collect();
+        let mut billboards = Mesh::new(
+            &billboard_points,
+            None,
+            &[(0, 3, gl::FLOAT), (1, 4, gl::FLOAT)],
+        )?;
+        billboards.set_mode(DrawMode::Points);
+        billboards.set_winding(Winding::Clockwise);
+
+        let billboard_program = ShaderProgram::builder()
+            .vertex(BILLBOARD_VERTEX_SHADER_PATH)
+            .geometry(BILLBOARD_GEOMETRY_SHADER_PATH)
+            .fragment(BILLBOARD_FRAGMENT_SHADER_PATH)
+            .with_binary_cache(SHADER_CACHE_DIR)
+            .build()?;
+
+        let ripple_corners = [
+            [-1.0, -0.6, -1.0],
+            [1.0, -0.6, -1.0],
+            [1.0, 0.6, -1.0],
+            [-1.0, 0.6, -1.0],
+        ];
+        let mut ripple = Mesh::new(&ripple_corners, None, &[(0, 3, gl::FLOAT)])?;
+        ripple.set_winding(Winding::Clockwise);
+
+        let ripple_program = ShaderProgram::builder()
+            .vertex(TESSELLATION_VERTEX_SHADER_PATH)
+            .tess_control(TESSELLATION_CONTROL_SHADER_PATH)
+            .tess_evaluation(TESSELLATION_EVALUATION_SHADER_PATH)
+            .fragment(TESSELLATION_FRAGMENT_SHADER_PATH)
+            .with_binary_cache(SHADER_CACHE_DIR)
+            .build()?;
+
+        check_compute_round_trip(app)?;
+
+        // Filled in every frame with `update_sub_data`, which needs the store allocated first.
+        let camera_ubo = Buffer::new_uniform()?;
+        camera_ubo.set_data(&[0; CAMERA_BLOCK_SIZE], BufferUsage::DynamicDraw);
+        camera_ubo.bind_base(FRAME_UBO_BINDING);
+        for program in [&app.shader_program, &billboard_program, &ripple_program] {
+            program.bind_default_uniform_block(FRAME_BLOCK)?;
+        }
+        gl_check!("camera uniform buffer setup");
 
-        while !window.should_close() {
-            glfw.poll_events();
+        let texture = Texture::from_file_or_checkerboard("textures/checker.png");
+        texture.bind(&mut app.render_state, 0);
+        app.render_state.use_program(app.shader_program.id);
+        app.shader_program.set_uniform_i32("uTexture", 0)?;
 
-            // gl::ClearColor(0.2, 0.3, 0.3, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+        // Every mesh declares its winding, so the cube's back faces can be culled.
+        app.render_state.set_cull_face(CullMode::Back);
+        gl_check!("texture and state setup");
 
-            // Draw
-            shader_program.apply();
-            gl::BindVertexArray(vertex_array);
+        // Everything a draw needs is bound by now.
+        if cfg!(debug_assertions) {
+            if let Err(err) = app.shader_program.validate() {
+                eprintln!("{}", err);
+            }
+        }
 
-            let uniform_name = CString::new("xPosition").unwrap();
-            let uniform_location_x =
-                gl::GetUniformLocation(shader_program.id, uniform_name.into_raw());
+        let (width, height) = app.window.get_framebuffer_size();
+        let offscreen = Framebuffer::with_color_texture(width, height)?;
+        Framebuffer::bind_default();
+        // Creating the color texture bound it over the one bound above.
+        app.render_state.invalidate_bindings();
+        gl_check!("offscreen framebuffer setup");
+
+        let mut camera = Camera::new([0.0, 0.0, 3.0]);
+        camera.resize(width, height);
+        let mut camera_path = CameraPath::new(
+            CAMERA_PATH
+                .into_iter()
+                .map(|(time, position, target)| CameraKeyframe {
+                    time,
+                    position,
+                    target,
+                })
+                .collect(),
+        );
+        camera_path.set_looping(true);
+
+        app.window.set_cursor_mode(CursorMode::Disabled);
+
+        Ok(Self {
+            offscreen,
+            camera,
+            camera_path,
+            camera_ubo,
+            camera_block_program: app.shader_program.id,
+            quad,
+            instance_buffer,
+            bobbing_quad_origin: instance_offsets[BOBBING_QUAD],
+            cube,
+            cube_transform,
+            ribbons,
+            outline,
+            pyramid,
+            pyramid_transform,
+            sprite,
+            circle_program,
+            fxaa: Fxaa::new(FXAA_QUALITY),
+            fxaa_program,
+            billboards,
+            billboard_program,
+            ripple,
+            ripple_program,
+            texture,
+            cursor_captured: true,
+            last_cursor: None,
+        })
+    }
+}
 
-            let uniform_name = CString::new("yPosition").unwrap();
-            let uniform_location_y =
-                gl::GetUniformLocation(shader_program.id, uniform_name.into_raw());
+impl Scene for DemoScene {
+    fn on_render(&mut self, app: &mut App, dt: f32) {
+        // Applied every frame rather than on key repeat events, whose rate is up to the OS, so
+        // the camera moves at its own speed for as long as a key is held.
+        for (key, movement) in MOVEMENT_KEYS {
+            if app.input.is_down(key) {
+                self.camera.process_keyboard(movement, dt);
+            }
+        }
 
-            // gl::Uniform1f(uniform_location, value);
+        if app.input.just_pressed(CURSOR_CAPTURE_KEY) {
+            self.cursor_captured = !self.cursor_captured;
+            let mode = if self.cursor_captured {
+                CursorMode::Disabled
+            } else {
+                CursorMode::Normal
+            };
+            app.window.set_cursor_mode(mode);
+            self.last_cursor = None;
+        }
 
-            // gl::DrawArrays(gl::TRIANGLES, 0, 6);
-            gl::DrawElements(
-                gl::TRIANGLES,
-                6 as i32,
-                gl::UNSIGNED_INT,
-                0 as *const c_void,
-            );
+        if app.input.just_pressed(CAMERA_PATH_KEY) {
+            if self.camera_path.is_playing() {
+                self.camera_path.pause();
+            } else {
+                self.camera_path.play();
+            }
+        }
+        // The path takes over the camera while it plays.
+        if self.camera_path.is_playing() {
+            self.camera_path.update(dt, &mut self.camera);
+        }
 
-            let movement = 0.02;
+        if app.input.just_pressed(FXAA_KEY) {
+            self.fxaa.enabled = !self.fxaa.enabled;
+            println!("FXAA {}", if self.fxaa.enabled { "on" } else { "off" });
+        }
 
-            window.swap_buffers();
-            for (_, event) in glfw::flush_messages(&events) {
-                match event {
-                    glfw::WindowEvent::Key(Key::Right, _, Action::Repeat, _) => {
-                        x_value += movement;
-                        gl::Uniform1f(uniform_location_x, x_value);
-                    }
-                    glfw::WindowEvent::Key(Key::Left, _, Action::Repeat, _) => {
-                        x_value -= movement;
-                        gl::Uniform1f(uniform_location_x, x_value);
-                    }
-                    glfw::WindowEvent::Key(Key::Up, _, Action::Repeat, _) => {
-                        y_value += movement;
-                        gl::Uniform1f(uniform_location_y, y_value);
-                    }
-                    glfw::WindowEvent::Key(Key::Down, _, Action::Repeat, _) => {
-                        y_value -= movement;
-                        gl::Uniform1f(uniform_location_y, y_value);
+        let spin =
+            Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0).normalize(), CUBE_SPIN_SPEED * dt);
+        self.cube_transform.rotation = (spin * self.cube_transform.rotation).normalize();
+
+        let (width, height) = app.window.get_framebuffer_size();
+        let projection = self.camera.projection_matrix();
+
+        let [bob_x, bob_y] = self.bobbing_quad_origin;
+        let bob = (app.glfw.get_time() as f32 * BOB_SPEED).sin() * BOB_HEIGHT;
+        let bob_bytes: Vec<u8> = [bob_x, bob_y + bob]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+
+        unsafe {
+            let stride = size_of::<[f32; 2]>() as isize;
+            let mapping = self.instance_buffer.map_range_mut(
+                BOBBING_QUAD as isize * stride,
+                stride,
+                gl::MAP_FLUSH_EXPLICIT_BIT | gl::MAP_INVALIDATE_RANGE_BIT,
+            );
+            if let Some(mut mapping) = mapping {
+                mapping.copy_from_slice(&bob_bytes);
+                mapping.flush(0, stride);
+            }
+            gl_check!("bobbing quad update");
+
+            let block = camera_block(projection, self.camera.view_matrix());
+            self.camera_ubo.update_sub_data(0, &block);
+            if self.camera_block_program != app.shader_program.id {
+                self.camera_block_program = app.shader_program.id;
+                if let Err(err) = app.shader_program.bind_default_uniform_block(FRAME_BLOCK) {
+                    eprintln!("Reloaded shaders don't use the camera: {}", err);
+                }
+            }
+            gl_check!("camera uniform buffer update");
+
+            self.offscreen.bind();
+            app.render_state.clear();
+
+            // The quad spans -0.2..0.2, scaled by 5 it covers clip space. It writes no depth, so
+            // the scene draws over it.
+            app.render_state.use_program(self.circle_program.id);
+            let screen = Transform {
+                scale: Vec3::splat(5.0),
+                ..Transform::new()
+            };
+            let circle_program = &self.circle_program;
+            circle_program
+                .set_uniform_mat4("uModel", &screen.model_matrix())
+                .ok();
+            circle_program
+                .set_uniform_vec2("uResolution", [width as f32, height as f32])
+                .ok();
+            app.render_state.set_depth_mask(false);
+            self.sprite.draw(&mut app.render_state);
+            app.render_state.set_depth_mask(true);
+            gl_check!("circle draw");
+
+            app.render_state.use_program(app.shader_program.id);
+            // The FXAA pass samples the previous frame on the same unit.
+            self.texture.bind(&mut app.render_state, 0);
+
+            // Locations are cached by the program, so this doesn't query GL every frame. A
+            // reloaded shader is free to drop any of the uniforms.
+            let program = &app.shader_program;
+            program
+                .set_uniform_mat4("uModel", &Transform::new().model_matrix())
+                .ok();
+            self.quad
+                .draw_instanced(&mut app.render_state, self.instance_buffer.len() as i32);
+            gl_check!("quad grid draw");
+
+            program
+                .set_uniform_mat4("uModel", &self.cube_transform.model_matrix())
+                .ok();
+            self.cube.draw(&mut app.render_state);
+            gl_check!("cube draw");
+
+            program
+                .set_uniform_mat4("uModel", &Transform::new().model_matrix())
+                .ok();
+            app.render_state.enable_primitive_restart(RESTART_INDEX);
+            self.ribbons.draw(&mut app.render_state);
+            app.render_state.disable_primitive_restart();
+            gl_check!("ribbons draw");
+
+            for (i, position) in OUTLINE_POSITIONS.into_iter().enumerate() {
+                let transform = Transform {
+                    position: Vec3::from(position),
+                    ..Transform::new()
+                };
+                program
+                    .set_uniform_mat4("uModel", &transform.model_matrix())
+                    .ok();
+                if i == 1 {
+                    app.render_state.enable_line_smooth();
+                }
+                self.outline.draw(&mut app.render_state);
+            }
+            // Line smoothing turned blending on.
+            app.render_state.disable_line_smooth();
+            app.render_state.set_blending(false);
+            gl_check!("outlines draw");
+
+            // OBJ files wind their faces counter-clockwise, unlike the rest of the scene, which
+            // the mesh takes care of.
+            program
+                .set_uniform_mat4("uModel", &self.pyramid_transform.model_matrix())
+                .ok();
+            self.pyramid.draw(&mut app.render_state);
+            gl_check!("pyramid draw");
+
+            app.render_state.use_program(self.billboard_program.id);
+            self.billboards.draw(&mut app.render_state);
+            gl_check!("billboards draw");
+
+            app.render_state.use_program(self.ripple_program.id);
+            self.ripple_program
+                .set_uniform_f32("uTime", app.glfw.get_time() as f32)
+                .ok();
+            self.ripple.draw_patches(&mut app.render_state, 4);
+            app.render_state.use_program(app.shader_program.id);
+            gl_check!("tessellated quad draw");
+
+            // Translucent quads are tested against the opaque depth but don't write their own, so
+            // one can't hide whatever is drawn behind it afterwards.
+            app.render_state.set_blending(true);
+            app.render_state.set_depth_mask(false);
+            for (position, tint) in TRANSLUCENT_QUADS {
+                let transform = Transform {
+                    position: Vec3::from(position),
+                    ..Transform::new()
+                };
+                program
+                    .set_uniform_mat4("uModel", &transform.model_matrix())
+                    .ok();
+                program.set_uniform_vec4("uTint", tint.to_array()).ok();
+                self.sprite.draw(&mut app.render_state);
+            }
+            program
+                .set_uniform_vec4("uTint", Color::WHITE.to_array())
+                .ok();
+            app.render_state.set_depth_mask(true);
+            app.render_state.set_blending(false);
+            gl_check!("translucent quads draw");
+
+            if app.input.button_just_pressed(PICK_BUTTON) {
+                let (x, y) = if self.cursor_captured {
+                    (width / 2, height / 2)
+                } else {
+                    // The cursor is in screen coordinates with y growing downwards, the
+                    // framebuffer in pixels with y growing upwards.
+                    let (cursor_x, cursor_y) = app.window.get_cursor_pos();
+                    let (x_scale, y_scale) = app.content_scale;
+                    let x = (cursor_x * x_scale as f64) as i32;
+                    let y = height - 1 - (cursor_y * y_scale as f64) as i32;
+                    (x, y)
+                };
+
+                // The depth buffer is the offscreen one, the window's only gets the color.
+                let depth = read_depth_from(self.offscreen.id(), x, y);
+                if depth < 1.0 {
+                    let distance = linearize_depth(depth, NEAR_PLANE, FAR_PLANE);
+                    println!("Distance under the cursor: {:.2}", distance);
+                } else {
+                    println!("Nothing under the cursor");
+                }
+                gl_check!("depth pick");
+            }
+
+            Framebuffer::bind_default();
+            if self.fxaa.enabled {
+                let scene = self.offscreen.color_texture().unwrap();
+                match self
+                    .fxaa
+                    .prepare(&mut app.render_state, &self.fxaa_program, scene)
+                {
+                    Ok(()) => {
+                        self.fxaa_program
+                            .set_uniform_mat4("uModel", &screen.model_matrix())
+                            .ok();
+                        let depth_test = app.render_state.depth_test();
+                        app.render_state.set_depth_test(false);
+                        self.sprite.draw(&mut app.render_state);
+                        app.render_state.set_depth_test(depth_test);
                     }
+                    Err(err) => eprintln!("Failed to set up the FXAA pass: {}", err),
+                }
+                gl_check!("fxaa pass");
+            } else {
+                self.offscreen.blit_to(None);
+                Framebuffer::bind_default();
+                gl_check!("offscreen blit");
+            }
 
-                    _ => {}
+            if app.input.just_pressed(SCREENSHOT_KEY) {
+                match renderer::capture_screenshot(SCREENSHOT_PATH) {
+                    Ok(()) => println!("Saved screenshot to {}", SCREENSHOT_PATH),
+                    Err(err) => eprintln!("Failed to save screenshot: {}", err),
                 }
+            }
+        }
+    }
 
-                handle_window_event(&mut window, event);
+    fn on_event(&mut self, app: &mut App, event: &WindowEvent) {
+        match *event {
+            // A minimized window has a zero-sized framebuffer, which can't have storage.
+            WindowEvent::FramebufferSize(width, height) if width > 0 && height > 0 => unsafe {
+                match Framebuffer::with_color_texture(width, height) {
+                    Ok(offscreen) => self.offscreen = offscreen,
+                    Err(err) => eprintln!("Failed to resize the offscreen framebuffer: {}", err),
+                }
+                Framebuffer::bind_default();
+                // Creating the new color texture bound it, and the old one's id may be reused.
+                app.render_state.invalidate_bindings();
+                self.camera.resize(width, height);
+            },
+            WindowEvent::CursorPos(x, y) => {
+                if let Some((last_x, last_y)) = self.last_cursor.replace((x, y)) {
+                    if self.cursor_captured {
+                        // Window y grows downwards, pitch grows upwards.
+                        self.camera
+                            .process_mouse((x - last_x) as f32, (last_y - y) as f32);
+                    }
+                }
             }
+            WindowEvent::Scroll(_, y_offset) => self.camera.process_scroll(y_offset as f32),
+            _ => {}
         }
-    };
+    }
 }
 
-fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent) {
-    match event {
-        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
-        glfw::WindowEvent::Key(Key::Num1, _, Action::Press, _) => unsafe {
-            println!("Wireframe OFF");
-            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
-        },
-        glfw::WindowEvent::Key(Key::Num2, _, Action::Press, _) => unsafe {
-            println!("Wireframe ON");
-            gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
-        },
-
-        _ => {}
+// Prints the wrappers' GL call log, see `--features gl-trace`.
+#[cfg(feature = "gl-trace")]
+struct GlTraceLogger;
+
+#[cfg(feature = "gl-trace")]
+impl log::Log for GlTraceLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == "gl"
     }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[gl] {}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() -> Result<(), Error> {
+    #[cfg(feature = "gl-trace")]
+    if log::set_logger(&GlTraceLogger).is_ok() {
+        log::set_max_level(log::LevelFilter::Trace);
+    }
+
+    // std::env::set_var("RUST_BACKTRACE", "1");
+
+    let mut app = App::new(
+        WindowConfig::default(),
+        VERTEX_SHADER_PATH,
+        FRAGMENT_SHADER_PATH,
+    )?;
+    let mut scene = unsafe { DemoScene::new(&mut app)? };
+
+    app.run(&mut scene);
+
+    // The scene's GL objects are deleted while the app still holds the context, which is what
+    // any errors reported here would be about. They are returned rather than just printed so a
+    // leak or double delete on the way out shows up in the exit status.
+    drop(scene);
+    debug::check_gl_error("scene teardown")?;
+
+    Ok(())
 }
@@ -0,0 +1,122 @@
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+// Matrices are returned as arrays of columns, the layout `ShaderProgram::set_uniform_mat4`
+// expects.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Scales, then rotates, then translates.
+    pub fn model_matrix(&self) -> [[f32; 4]; 4] {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+            .to_cols_array_2d()
+    }
+}
+
+// Transforms normals the way `model` transforms positions: the inverse transpose of its upper-left
+// 3x3. Unlike `model` itself, it keeps normals perpendicular to surfaces under non-uniform scale.
+// The result isn't normalized. For `ShaderProgram::set_uniform_mat3`.
+pub fn normal_matrix(model: [[f32; 4]; 4]) -> [[f32; 3]; 3] {
+    Mat3::from_mat4(Mat4::from_cols_array_2d(&model))
+        .inverse()
+        .transpose()
+        .to_cols_array_2d()
+}
+
+// Right-handed, mapping depth to GL's -1..1 clip range. `fov_y` is in radians.
+pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> [[f32; 4]; 4] {
+    Mat4::perspective_rh_gl(fov_y, aspect, near, far).to_cols_array_2d()
+}
+
+// For `RenderState::set_reversed_z`: right-handed, mapping the near plane to depth 1 and
+// infinity to 0 in a 0..1 clip range. `fov_y` is in radians.
+pub fn perspective_reversed_z(fov_y: f32, aspect: f32, near: f32) -> [[f32; 4]; 4] {
+    Mat4::perspective_infinite_reverse_rh(fov_y, aspect, near).to_cols_array_2d()
+}
+
+pub fn orthographic(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+) -> [[f32; 4]; 4] {
+    Mat4::orthographic_rh_gl(left, right, bottom, top, near, far).to_cols_array_2d()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    fn depth(projection: [[f32; 4]; 4], z: f32) -> f32 {
+        let clip = Mat4::from_cols_array_2d(&projection) * Vec4::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one() {
+        let projection = perspective_reversed_z(1.0, 1.5, 0.1);
+
+        assert!((depth(projection, -0.1) - 1.0).abs() < 1e-6);
+        assert!(depth(projection, -1.0) < depth(projection, -0.5));
+        assert!(depth(projection, -1.0e6) < 1e-6);
+    }
+
+    #[test]
+    fn orthographic_maps_box_to_clip_space() {
+        let projection = Mat4::from_cols_array_2d(&orthographic(0.0, 800.0, 0.0, 600.0, 0.1, 10.0));
+
+        let corner = projection * Vec4::new(0.0, 0.0, -0.1, 1.0);
+        assert!(
+            (corner - Vec4::new(-1.0, -1.0, -1.0, 1.0))
+                .abs()
+                .max_element()
+                < 1e-6
+        );
+
+        let corner = projection * Vec4::new(800.0, 600.0, -10.0, 1.0);
+        assert!((corner - Vec4::new(1.0, 1.0, 1.0, 1.0)).abs().max_element() < 1e-6);
+
+        // No perspective divide.
+        assert_eq!(projection.row(3), Vec4::W);
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let transform = Transform {
+            scale: Vec3::new(2.0, 1.0, 1.0),
+            ..Transform::new()
+        };
+        let model = Mat4::from_cols_array_2d(&transform.model_matrix());
+        let normal_matrix = Mat3::from_cols_array_2d(&normal_matrix(transform.model_matrix()));
+
+        // A surface sloping at 45 degrees, stretched along x.
+        let tangent = model.transform_vector3(Vec3::new(1.0, -1.0, 0.0));
+        let normal = normal_matrix * Vec3::new(1.0, 1.0, 0.0);
+
+        assert!(tangent.dot(normal).abs() < 1e-6);
+        // Transforming the normal like a position would tilt it off the surface.
+        assert!(tangent.dot(model.transform_vector3(Vec3::new(1.0, 1.0, 0.0))) > 1.0);
+    }
+}
@@ -0,0 +1,577 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use gl::types::*;
+use thiserror::Error;
+
+use crate::buffers::{Buffer, BufferError, BufferUsage, VertexArray};
+use crate::debug;
+use crate::render_state::{RenderState, Winding};
+use crate::renderer::{self, DrawMode};
+
+// Interleaved vertices of type `V`, optionally indexed, drawn as triangles unless another mode
+// is set. `layout` lists each attribute in `V` as (location, component count, component type), in
+// field order; see `VertexArray::add_attribute`.
+//
+// Draws set the front face to the mesh's winding, counter-clockwise unless set otherwise, so
+// back-face culling keeps the right side of every mesh whichever way it is wound.
+pub struct Mesh<V> {
+    vertex_array: VertexArray,
+    vertex_buffer: Buffer<V>,
+    index_buffer: Option<Buffer<u32>>,
+    mode: DrawMode,
+    winding: Winding,
+}
+
+// Everything `Mesh::from_descriptor` needs to set up a mesh in one call. `layout` is as for
+// `Mesh`, and `usage` applies to both buffers.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshDescriptor<'a, V> {
+    pub vertices: &'a [V],
+    pub indices: Option<&'a [u32]>,
+    pub layout: &'a [(u32, i32, GLenum)],
+    pub usage: BufferUsage,
+}
+
+impl<V> Mesh<V> {
+    // Static geometry, see `from_descriptor`.
+    pub unsafe fn new(
+        vertices: &[V],
+        indices: Option<&[u32]>,
+        layout: &[(u32, i32, GLenum)],
+    ) -> Result<Self, BufferError> {
+        Self::from_descriptor(&MeshDescriptor {
+            vertices,
+            indices,
+            layout,
+            usage: BufferUsage::StaticDraw,
+        })
+    }
+
+    // Creates the vertex array, uploads the vertices and indices and applies the layout.
+    pub unsafe fn from_descriptor(descriptor: &MeshDescriptor<V>) -> Result<Self, BufferError> {
+        debug::assert_context_current();
+        let mut vertex_array = VertexArray::new()?;
+        vertex_array.bind_uncached();
+
+        let vertex_buffer = Buffer::new(gl::ARRAY_BUFFER)?;
+        vertex_buffer.set_data(descriptor.vertices, descriptor.usage);
+
+        for &(location, count, data_type) in descriptor.layout {
+            vertex_array.add_attribute(location, count, data_type);
+        }
+        vertex_array.finish()?;
+
+        // The element buffer binding is part of the vertex array state, so it has to be bound
+        // while the vertex array is.
+        let index_buffer = match descriptor.indices {
+            Some(indices) => {
+                let index_buffer = Buffer::new(gl::ELEMENT_ARRAY_BUFFER)?;
+                index_buffer.set_data(indices, descriptor.usage);
+                Some(index_buffer)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            vertex_array,
+            vertex_buffer,
+            index_buffer,
+            mode: DrawMode::Triangles,
+            winding: Winding::CounterClockwise,
+        })
+    }
+}
+
+impl<V> Mesh<V> {
+    // For adding attributes from other buffers, like per-instance data.
+    pub fn vertex_array(&self) -> &VertexArray {
+        &self.vertex_array
+    }
+
+    pub fn set_mode(&mut self, mode: DrawMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> DrawMode {
+        self.mode
+    }
+
+    // The order in which the front faces' vertices appear on screen.
+    pub fn set_winding(&mut self, winding: Winding) {
+        self.winding = winding;
+    }
+
+    pub fn winding(&self) -> Winding {
+        self.winding
+    }
+
+    // Number of vertices a draw processes: the index count for indexed meshes, the vertex count
+    // otherwise.
+    pub fn count(&self) -> usize {
+        match &self.index_buffer {
+            Some(index_buffer) => index_buffer.len(),
+            None => self.vertex_buffer.len(),
+        }
+    }
+}
+
+impl<V> Mesh<V> {
+    pub unsafe fn draw(&self, render_state: &mut RenderState) {
+        render_state.set_front_face(self.winding);
+        match &self.index_buffer {
+            Some(index_buffer) => {
+                renderer::draw_indexed(render_state, &self.vertex_array, self.mode, index_buffer)
+            }
+            None => renderer::draw(
+                render_state,
+                &self.vertex_array,
+                self.mode,
+                self.count() as i32,
+            ),
+        }
+    }
+
+    pub unsafe fn draw_instanced(&self, render_state: &mut RenderState, instance_count: i32) {
+        render_state.set_front_face(self.winding);
+        match &self.index_buffer {
+            Some(index_buffer) => renderer::draw_indexed_instanced(
+                render_state,
+                &self.vertex_array,
+                self.mode,
+                index_buffer,
+                instance_count,
+            ),
+            None => renderer::draw_instanced(
+                render_state,
+                &self.vertex_array,
+                self.mode,
+                self.count() as i32,
+                instance_count,
+            ),
+        }
+    }
+
+    // Draws the vertices as patches, see `renderer::draw_patches`. The winding applies to the
+    // triangles the tessellator emits, which is set by the evaluation shader's layout.
+    pub unsafe fn draw_patches(&self, render_state: &mut RenderState, vertices_per_patch: i32) {
+        render_state.set_front_face(self.winding);
+        renderer::draw_patches(
+            render_state,
+            &self.vertex_array,
+            vertices_per_patch,
+            self.count() as i32,
+        );
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ObjError {
+    #[error{"{0}"}]
+    Io(#[from] std::io::Error),
+    #[error{"{0}"}]
+    Buffer(#[from] BufferError),
+    #[error("Malformed OBJ file, line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+// Vertex format of meshes loaded by `Mesh::from_obj`, at attribute locations 0 (position),
+// 1 (normal) and 2 (texture coordinates). Missing normals and texture coordinates are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+}
+
+impl Mesh<ObjVertex> {
+    // Loads the geometry of a Wavefront OBJ file as one indexed triangle mesh, wound
+    // counter-clockwise like the format specifies. Objects, groups and materials are ignored.
+    pub unsafe fn from_obj(path: impl AsRef<Path>) -> Result<Self, ObjError> {
+        let source = fs::read_to_string(path)?;
+        let (vertices, indices) = parse_obj(&source)?;
+        let layout = [(0, 3, gl::FLOAT), (1, 3, gl::FLOAT), (2, 2, gl::FLOAT)];
+
+        let mut mesh = Self::new(&vertices, Some(&indices), &layout)?;
+        mesh.set_winding(Winding::CounterClockwise);
+
+        Ok(mesh)
+    }
+}
+
+// Faces with more than three corners are split into a fan around their first corner, which is
+// right for the convex polygons exporters write. Corners sharing the same position, texture
+// coordinate and normal indices share a vertex.
+pub fn parse_obj(source: &str) -> Result<(Vec<ObjVertex>, Vec<u32>), ObjError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut vertices: Vec<ObjVertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: String| ObjError::Parse {
+            line: line_number,
+            message,
+        };
+
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(parse_floats(&arguments).map_err(error)?),
+            "vt" => tex_coords.push(parse_floats(&arguments).map_err(error)?),
+            "vn" => normals.push(parse_floats(&arguments).map_err(error)?),
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "face with {} corners, at least 3 are needed",
+                        arguments.len()
+                    )));
+                }
+
+                let mut corners = Vec::with_capacity(arguments.len());
+                for corner in &arguments {
+                    let key =
+                        parse_corner(corner, positions.len(), tex_coords.len(), normals.len())
+                            .map_err(error)?;
+
+                    let index = *vertex_indices.entry(key).or_insert_with(|| {
+                        let (position, tex_coord, normal) = key;
+                        vertices.push(ObjVertex {
+                            position: positions[position],
+                            normal: normal.map_or([0.0; 3], |normal| normals[normal]),
+                            tex_coord: tex_coord
+                                .map_or([0.0; 2], |tex_coord| tex_coords[tex_coord]),
+                        });
+                        (vertices.len() - 1) as u32
+                    });
+                    corners.push(index);
+                }
+
+                for i in 1..corners.len() - 1 {
+                    indices.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            // Comments, objects, groups, smoothing groups and materials.
+            _ => {}
+        }
+    }
+
+    Ok((vertices, indices))
+}
+
+// Parses the first N numbers of a `v`, `vt` or `vn` line. Extra components (like the optional w)
+// are ignored.
+fn parse_floats<const N: usize>(arguments: &[&str]) -> Result<[f32; N], String> {
+    if arguments.len() < N {
+        return Err(format!("expected {} numbers, found {}", N, arguments.len()));
+    }
+
+    let mut values = [0.0; N];
+    for (value, argument) in values.iter_mut().zip(arguments) {
+        *value = argument
+            .parse()
+            .map_err(|_| format!("invalid number \"{}\"", argument))?;
+    }
+
+    Ok(values)
+}
+
+// Parses a face corner (`v`, `v/vt`, `v//vn` or `v/vt/vn`) into zero-based indices, given how many
+// of each element have been declared so far.
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    tex_coord_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = corner.split('/');
+    let position = parts.next().unwrap_or("");
+    let tex_coord = parts.next().filter(|part| !part.is_empty());
+    let normal = parts.next().filter(|part| !part.is_empty());
+
+    let position = resolve_index(position, position_count)?;
+    let tex_coord = tex_coord
+        .map(|index| resolve_index(index, tex_coord_count))
+        .transpose()?;
+    let normal = normal
+        .map(|index| resolve_index(index, normal_count))
+        .transpose()?;
+
+    Ok((position, tex_coord, normal))
+}
+
+// OBJ indices start at 1, and negative ones count back from the last element declared.
+fn resolve_index(index: &str, count: usize) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid index \"{}\"", index))?;
+
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range, {} declared", value, count));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::GlSpy;
+
+    const CUBE: &str = "\
+# unit cube
+o cube
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vn 0 0 -1
+vn 0 0 1
+vn -1 0 0
+vn 1 0 0
+vn 0 -1 0
+vn 0 1 0
+f 1//1 4//1 3//1 2//1
+f 5//2 6//2 7//2 8//2
+f 1//3 5//3 8//3 4//3
+f 2//4 3//4 7//4 6//4
+f 1//5 2//5 6//5 5//5
+f 4//6 8//6 7//6 3//6
+";
+
+    fn parse_error(source: &str) -> (usize, String) {
+        match parse_obj(source) {
+            Err(ObjError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parses_cube() {
+        let (vertices, indices) = parse_obj(CUBE).unwrap();
+
+        // Every corner has a different normal on each of its three faces.
+        assert_eq!(vertices.len(), 24);
+        // Six quads split into two triangles each.
+        assert_eq!(indices.len(), 36);
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+
+        assert_eq!(vertices[0].position, [0.0, 0.0, 0.0]);
+        assert_eq!(vertices[0].normal, [0.0, 0.0, -1.0]);
+        assert_eq!(vertices[0].tex_coord, [0.0, 0.0]);
+        // The first quad is fanned around its first corner.
+        assert_eq!(&indices[..6], &[0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0.5 0.5
+f -3/-1 -2/-1 -1/-1
+v 5 5 5
+f 1 -1 2
+";
+        let (vertices, indices) = parse_obj(source).unwrap();
+
+        assert_eq!(vertices[0].position, [0.0, 0.0, 0.0]);
+        assert_eq!(vertices[2].position, [0.0, 1.0, 0.0]);
+        assert_eq!(vertices[2].tex_coord, [0.5, 0.5]);
+        // -1 counts back from the last position declared before the face.
+        let last = vertices[indices[4] as usize];
+        assert_eq!(last.position, [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn shares_identical_corners() {
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+f 1/1/1 2/1/1 3/1/1
+f 2/1/1 4/1/1 3/1/1
+f 1/2/1 2/1/1 3/1/1
+";
+        let (vertices, indices) = parse_obj(source).unwrap();
+
+        // Corners 2/1/1 and 3/1/1 are reused, 1/2/1 differs from 1/1/1 in its texture coordinate.
+        assert_eq!(vertices.len(), 5);
+        assert_eq!(indices, vec![0, 1, 2, 1, 3, 2, 4, 1, 2]);
+        assert_eq!(vertices[4].tex_coord, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn reports_too_few_corners() {
+        let (line, message) = parse_error("v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert_eq!(line, 3);
+        assert!(message.contains("2 corners"), "{}", message);
+    }
+
+    #[test]
+    fn reports_too_few_numbers() {
+        let (line, message) = parse_error("v 0 0\n");
+        assert_eq!(line, 1);
+        assert!(message.contains("expected 3 numbers"), "{}", message);
+    }
+
+    #[test]
+    fn reports_invalid_number() {
+        let (line, message) = parse_error("# comment\nvt 0 x\n");
+        assert_eq!(line, 2);
+        assert!(message.contains("invalid number \"x\""), "{}", message);
+    }
+
+    #[test]
+    fn reports_invalid_index() {
+        let (line, message) = parse_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 a 3\n");
+        assert_eq!(line, 4);
+        assert!(message.contains("invalid index \"a\""), "{}", message);
+    }
+
+    #[test]
+    fn reports_out_of_range_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+        for face in [
+            "f 1 2 4",
+            "f 0 1 2",
+            "f -4 1 2",
+            "f 1/1 2/1 3/1",
+            "f 1//1 2//1 3//1",
+        ] {
+            let (line, message) = parse_error(&format!("{}{}\n", source, face));
+            assert_eq!(line, 4, "{}", face);
+            assert!(message.contains("out of range"), "{}: {}", face, message);
+        }
+    }
+
+    #[test]
+    fn builds_a_quad_from_a_descriptor() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 16);
+        let vertices: [[f32; 5]; 4] = [
+            [0.5, 0.5, 0.0, 1.0, 1.0],
+            [0.5, -0.5, 0.0, 1.0, 0.0],
+            [-0.5, -0.5, 0.0, 0.0, 0.0],
+            [-0.5, 0.5, 0.0, 0.0, 1.0],
+        ];
+        let indices = [0, 1, 3, 1, 2, 3];
+
+        let quad = unsafe {
+            Mesh::from_descriptor(&MeshDescriptor {
+                vertices: &vertices,
+                indices: Some(&indices),
+                layout: &[(0, 3, gl::FLOAT), (1, 2, gl::FLOAT)],
+                usage: BufferUsage::DynamicDraw,
+            })
+            .unwrap()
+        };
+
+        assert_eq!(quad.count(), 6);
+        assert_eq!(quad.mode(), DrawMode::Triangles);
+        let calls = spy.calls();
+        for (target, size) in [(gl::ARRAY_BUFFER, 80), (gl::ELEMENT_ARRAY_BUFFER, 24)] {
+            let upload = format!(
+                "glBufferData({:#x}, {}, {:#x})",
+                target,
+                size,
+                gl::DYNAMIC_DRAW
+            );
+            assert!(calls.contains(&upload), "missing {}", upload);
+        }
+        for (location, size, offset) in [(0, 3, 0), (1, 2, 12)] {
+            let pointer = format!(
+                "glVertexAttribPointer({}, {}, {:#x}, 20, {})",
+                location,
+                size,
+                gl::FLOAT,
+                offset
+            );
+            assert!(calls.contains(&pointer), "missing {}", pointer);
+        }
+    }
+
+    #[test]
+    fn sample_quad_winds_clockwise() {
+        // The demo's quad: top right, bottom right, bottom left, top left.
+        let corners = [
+            [0.2, 0.2, 0.0],
+            [0.2, -0.2, 0.0],
+            [-0.2, -0.2, 0.0],
+            [-0.2, 0.2, 0.0],
+        ];
+        let indices = [0, 1, 3, 1, 2, 3];
+
+        for triangle in indices.chunks(3) {
+            let winding = Winding::of_triangle(
+                corners[triangle[0]],
+                corners[triangle[1]],
+                corners[triangle[2]],
+            );
+            assert_eq!(winding, Winding::Clockwise);
+        }
+    }
+
+    #[test]
+    fn draws_set_the_front_face_to_the_winding() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 16);
+        let mut render_state = RenderState::new();
+
+        let path =
+            std::env::temp_dir().join(format!("opengl_rust_cube_{}.obj", std::process::id()));
+        fs::write(&path, CUBE).unwrap();
+
+        unsafe {
+            let cube = Mesh::from_obj(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(cube.winding(), Winding::CounterClockwise);
+
+            let mut quad = Mesh::new(&[[0.0f32; 3]; 3], None, &[(0, 3, gl::FLOAT)]).unwrap();
+            quad.set_winding(Winding::Clockwise);
+            quad.draw(&mut render_state);
+            cube.draw(&mut render_state);
+            quad.draw(&mut render_state);
+        }
+
+        let front_faces: Vec<_> = spy
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("glFrontFace"))
+            .collect();
+        assert_eq!(
+            front_faces,
+            [
+                format!("glFrontFace({:#x})", gl::CW),
+                format!("glFrontFace({:#x})", gl::CCW),
+                format!("glFrontFace({:#x})", gl::CW),
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct CpuStats {
+    timings: HashMap<&'static str, Duration>,
+}
+
+impl CpuStats {
+    pub fn get(&self, name: &str) -> Option<Duration> {
+        self.timings.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.timings.iter().map(|(name, time)| (*name, *time))
+    }
+}
+
+thread_local! {
+    static CPU_STATS: RefCell<CpuStats> = RefCell::new(CpuStats::default());
+}
+
+// Records the time between its creation and drop under `name`. Scopes with the same name
+// entered more than once in a frame add up.
+pub struct CpuScope {
+    name: &'static str,
+    start: Instant,
+}
+
+impl CpuScope {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for CpuScope {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        CPU_STATS.with(|stats| {
+            *stats.borrow_mut().timings.entry(self.name).or_default() += elapsed;
+        });
+    }
+}
+
+// Takes the timings recorded on this thread since the last call, starting a new frame.
+pub fn end_frame() -> CpuStats {
+    CPU_STATS.with(|stats| stats.take())
+}
+
+// Times the rest of the enclosing block: `cpu_scope!("upload");`
+#[macro_export]
+macro_rules! cpu_scope {
+    ($name:expr) => {
+        let _cpu_scope = $crate::profiling::CpuScope::new($name);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn records_nested_scopes() {
+        end_frame();
+
+        {
+            cpu_scope!("outer");
+            thread::sleep(Duration::from_millis(2));
+            {
+                cpu_scope!("inner");
+                thread::sleep(Duration::from_millis(2));
+            }
+        }
+
+        let stats = end_frame();
+        let outer = stats.get("outer").unwrap();
+        let inner = stats.get("inner").unwrap();
+        assert!(inner >= Duration::from_millis(2), "{:?}", inner);
+        assert!(outer >= inner + Duration::from_millis(2), "{:?}", outer);
+        assert_eq!(stats.iter().count(), 2);
+
+        // The next frame starts empty.
+        assert!(end_frame().get("outer").is_none());
+    }
+
+    #[test]
+    fn repeated_scopes_add_up() {
+        end_frame();
+
+        for _ in 0..2 {
+            cpu_scope!("repeated");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(end_frame().get("repeated").unwrap() >= Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,43 @@
+use std::os::raw::c_void;
+
+use gl::types::*;
+
+use crate::debug;
+use crate::render_state::get_integer;
+
+// Reads the depth value at pixel (x, y) of the default framebuffer. Coordinates are framebuffer
+// pixels with the origin at the bottom-left, so a GLFW cursor position has to be scaled by the
+// content scale and have its y flipped first.
+pub unsafe fn read_depth(x: i32, y: i32) -> f32 {
+    debug::assert_context_current();
+    read_depth_from(0, x, y)
+}
+
+// Same as `read_depth` for any framebuffer object. The previous read framebuffer binding is
+// restored afterwards.
+pub unsafe fn read_depth_from(framebuffer: u32, x: i32, y: i32) -> f32 {
+    debug::assert_context_current();
+    let previous_framebuffer = get_integer(gl::READ_FRAMEBUFFER_BINDING) as u32;
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+
+    let mut depth: GLfloat = 1.0;
+    gl::ReadPixels(
+        x,
+        y,
+        1,
+        1,
+        gl::DEPTH_COMPONENT,
+        gl::FLOAT,
+        &mut depth as *mut GLfloat as *mut c_void,
+    );
+
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_framebuffer);
+    depth
+}
+
+// Converts a [0, 1] depth buffer value written with a standard perspective projection back into
+// the distance from the camera along its view axis.
+pub fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
+    let ndc_depth = depth * 2.0 - 1.0;
+    (2.0 * near * far) / (far + near - ndc_depth * (far - near))
+}
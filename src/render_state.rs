@@ -0,0 +1,860 @@
+use std::collections::HashMap;
+
+use gl::types::*;
+
+use crate::color::Color;
+use crate::debug;
+
+// Order in which a triangle's vertices appear on screen for it to count as front-facing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Winding {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            Winding::Clockwise => gl::CW,
+            Winding::CounterClockwise => gl::CCW,
+        }
+    }
+
+    // The winding of a triangle seen from +z looking down -z, the way the camera starts out, by
+    // the sign of its area in the xy plane.
+    pub fn of_triangle(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Self {
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+        if area < 0.0 {
+            Winding::Clockwise
+        } else {
+            Winding::CounterClockwise
+        }
+    }
+}
+
+// Which faces are discarded before rasterization, based on their winding; see
+// `RenderState::set_front_face`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+    FrontAndBack,
+}
+
+impl CullMode {
+    // None for `CullMode::None`, which disables culling rather than naming a face.
+    pub fn to_gl(self) -> Option<GLenum> {
+        match self {
+            CullMode::None => None,
+            CullMode::Back => Some(gl::BACK),
+            CullMode::Front => Some(gl::FRONT),
+            CullMode::FrontAndBack => Some(gl::FRONT_AND_BACK),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+}
+
+impl BlendFactor {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            BlendFactor::Zero => gl::ZERO,
+            BlendFactor::One => gl::ONE,
+            BlendFactor::SrcColor => gl::SRC_COLOR,
+            BlendFactor::OneMinusSrcColor => gl::ONE_MINUS_SRC_COLOR,
+            BlendFactor::DstColor => gl::DST_COLOR,
+            BlendFactor::OneMinusDstColor => gl::ONE_MINUS_DST_COLOR,
+            BlendFactor::SrcAlpha => gl::SRC_ALPHA,
+            BlendFactor::OneMinusSrcAlpha => gl::ONE_MINUS_SRC_ALPHA,
+            BlendFactor::DstAlpha => gl::DST_ALPHA,
+            BlendFactor::OneMinusDstAlpha => gl::ONE_MINUS_DST_ALPHA,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendEquation {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendEquation {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            BlendEquation::Add => gl::FUNC_ADD,
+            BlendEquation::Subtract => gl::FUNC_SUBTRACT,
+            BlendEquation::ReverseSubtract => gl::FUNC_REVERSE_SUBTRACT,
+            BlendEquation::Min => gl::MIN,
+            BlendEquation::Max => gl::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlendFunc {
+    pub src_color: BlendFactor,
+    pub dst_color: BlendFactor,
+    pub src_alpha: BlendFactor,
+    pub dst_alpha: BlendFactor,
+}
+
+impl BlendFunc {
+    pub fn new(src: BlendFactor, dst: BlendFactor) -> Self {
+        Self {
+            src_color: src,
+            dst_color: dst,
+            src_alpha: src,
+            dst_alpha: dst,
+        }
+    }
+
+    // Source colors already multiplied by their alpha, e.g. when compositing into a texture.
+    pub fn premultiplied_alpha() -> Self {
+        Self::new(BlendFactor::One, BlendFactor::OneMinusSrcAlpha)
+    }
+}
+
+pub struct RenderState {
+    clear_color: Color,
+    clear_depth: GLdouble,
+    reversed_z: bool,
+    depth_test: bool,
+    depth_func: GLenum,
+    depth_mask: bool,
+    front_face: Winding,
+    cull_face: CullMode,
+    blending: bool,
+    blend_func: BlendFunc,
+    blend_equation: (BlendEquation, BlendEquation),
+    min_sample_shading: f32,
+    depth_clamp: bool,
+    primitive_restart: Option<u32>,
+    line_smooth: bool,
+    bindings: BindingCache,
+}
+
+// Last program, vertex array and 2D texture per unit bound through RenderState. `None`, or a unit
+// missing from `textures`, means unknown, which always forces the next bind through to GL.
+#[derive(Debug, Default)]
+struct BindingCache {
+    program: Option<u32>,
+    vertex_array: Option<u32>,
+    active_texture_unit: Option<u32>,
+    textures: HashMap<u32, u32>,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self {
+            clear_color: Color::TRANSPARENT,
+            clear_depth: 1.0,
+            reversed_z: false,
+            depth_test: false,
+            depth_func: gl::LESS,
+            depth_mask: true,
+            front_face: Winding::CounterClockwise,
+            cull_face: CullMode::None,
+            blending: false,
+            blend_func: BlendFunc::new(BlendFactor::One, BlendFactor::Zero),
+            blend_equation: (BlendEquation::Add, BlendEquation::Add),
+            min_sample_shading: 0.0,
+            depth_clamp: false,
+            primitive_restart: None,
+            line_smooth: false,
+            bindings: BindingCache::default(),
+        }
+    }
+}
+
+impl RenderState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RenderState {
+    pub unsafe fn set_clear_color(&mut self, color: Color) {
+        debug::assert_context_current();
+        self.clear_color = color;
+        gl_trace!("glClearColor({:?})", color);
+        gl::ClearColor(color.r, color.g, color.b, color.a);
+    }
+
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    // Clears the color buffer, and the depth buffer too while depth testing is on, to the
+    // configured clear values.
+    pub unsafe fn clear(&self) {
+        debug::assert_context_current();
+        let mut mask = gl::COLOR_BUFFER_BIT;
+        if self.depth_test {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+
+        gl_trace!("glClear({:#x})", mask);
+        gl::Clear(mask);
+    }
+}
+
+impl RenderState {
+    // 1.0 for the usual depth range, 0.0 when rendering with reversed-Z.
+    pub unsafe fn set_clear_depth(&mut self, depth: GLdouble) {
+        debug::assert_context_current();
+        self.clear_depth = depth;
+        gl_trace!("glClearDepth({})", depth);
+        gl::ClearDepth(depth);
+    }
+
+    pub fn clear_depth(&self) -> GLdouble {
+        self.clear_depth
+    }
+
+    // Reversed-Z maps the near plane to depth 1 and the far plane to 0, which spreads float depth
+    // precision far more evenly over the scene than GL's default -1..1 range. It takes three
+    // changes that have to agree: a 0..1 clip depth range (glClipControl), a depth test that
+    // passes for greater values and a clear depth of 0. Geometry also needs a reversed projection,
+    // see `math::perspective_reversed_z`.
+    //
+    // glClipControl is core in GL 4.5 (ARB_clip_control before that). Without it this returns
+    // false and leaves the depth setup alone. The function pointer alone doesn't tell, loaders
+    // resolve it on drivers whose context doesn't support it.
+    pub unsafe fn set_reversed_z(&mut self, enabled: bool) -> bool {
+        debug::assert_context_current();
+        let version = (
+            get_integer(gl::MAJOR_VERSION),
+            get_integer(gl::MINOR_VERSION),
+        );
+        if version < (4, 5) && !debug::has_extension("GL_ARB_clip_control") {
+            return false;
+        }
+
+        self.reversed_z = enabled;
+        let (depth_mode, depth_func, clear_depth) = if enabled {
+            (gl::ZERO_TO_ONE, gl::GREATER, 0.0)
+        } else {
+            (gl::NEGATIVE_ONE_TO_ONE, gl::LESS, 1.0)
+        };
+        gl_trace!("glClipControl(GL_LOWER_LEFT, {:#x})", depth_mode);
+        gl::ClipControl(gl::LOWER_LEFT, depth_mode);
+        self.set_depth_func(depth_func);
+        self.set_clear_depth(clear_depth);
+
+        true
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.reversed_z
+    }
+
+    // Depth testing needs a depth buffer, and the depth buffer has to be cleared every frame along
+    // with the color buffer.
+    pub unsafe fn set_depth_test(&mut self, enabled: bool) {
+        debug::assert_context_current();
+        self.depth_test = enabled;
+        if enabled {
+            gl_trace!("glEnable(GL_DEPTH_TEST)");
+            gl::Enable(gl::DEPTH_TEST);
+        } else {
+            gl_trace!("glDisable(GL_DEPTH_TEST)");
+            gl::Disable(gl::DEPTH_TEST);
+        }
+    }
+
+    pub fn depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    // gl::LESS by default; gl::LEQUAL lets a second pass draw over the same geometry, gl::GREATER
+    // goes with reversed-Z.
+    pub unsafe fn set_depth_func(&mut self, func: GLenum) {
+        debug::assert_context_current();
+        self.depth_func = func;
+        gl_trace!("glDepthFunc({:#x})", func);
+        gl::DepthFunc(func);
+    }
+
+    pub fn depth_func(&self) -> GLenum {
+        self.depth_func
+    }
+
+    // Controls depth writes only; depth testing is toggled separately. Transparent geometry is
+    // usually drawn after the opaque pass with the test on and writes off, so it is hidden behind
+    // opaque objects without hiding other transparent surfaces behind it.
+    pub unsafe fn set_depth_mask(&mut self, write: bool) {
+        debug::assert_context_current();
+        self.depth_mask = write;
+        gl_trace!("glDepthMask({})", write);
+        gl::DepthMask(if write { gl::TRUE } else { gl::FALSE });
+    }
+
+    pub fn depth_mask(&self) -> bool {
+        self.depth_mask
+    }
+
+    // Skips the GL call when the winding is already current, so it is cheap to call per mesh.
+    pub unsafe fn set_front_face(&mut self, winding: Winding) {
+        debug::assert_context_current();
+        if self.front_face != winding {
+            self.front_face = winding;
+            gl_trace!("glFrontFace({:?})", winding);
+            gl::FrontFace(winding.to_gl());
+        }
+    }
+
+    pub fn front_face(&self) -> Winding {
+        self.front_face
+    }
+
+    // Back-face culling skips the inside of closed meshes for free, as long as their winding
+    // matches the front face.
+    pub unsafe fn set_cull_face(&mut self, mode: CullMode) {
+        debug::assert_context_current();
+        self.cull_face = mode;
+        gl_trace!("cull face {:?}", mode);
+
+        match mode.to_gl() {
+            Some(face) => {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(face);
+            }
+            None => gl::Disable(gl::CULL_FACE),
+        }
+    }
+
+    pub fn cull_face(&self) -> CullMode {
+        self.cull_face
+    }
+}
+
+impl RenderState {
+    // Blending mixes each fragment into the framebuffer according to the blend function, instead
+    // of overwriting it. Translucent geometry should be drawn after everything opaque, furthest
+    // first.
+    pub unsafe fn set_blending(&mut self, enabled: bool) {
+        debug::assert_context_current();
+        self.blending = enabled;
+        if enabled {
+            gl_trace!("glEnable(GL_BLEND)");
+            gl::Enable(gl::BLEND);
+        } else {
+            gl_trace!("glDisable(GL_BLEND)");
+            gl::Disable(gl::BLEND);
+        }
+    }
+
+    pub fn blending(&self) -> bool {
+        self.blending
+    }
+
+    // The same factors for color and alpha. `set_blend_func(SrcAlpha, OneMinusSrcAlpha)` is
+    // regular alpha blending.
+    pub unsafe fn set_blend_func(&mut self, src: BlendFactor, dst: BlendFactor) {
+        self.set_blend_func_separate(BlendFunc::new(src, dst));
+    }
+
+    pub unsafe fn set_blend_func_separate(&mut self, func: BlendFunc) {
+        debug::assert_context_current();
+        self.blend_func = func;
+        gl_trace!("glBlendFuncSeparate({:?})", func);
+        gl::BlendFuncSeparate(
+            func.src_color.to_gl(),
+            func.dst_color.to_gl(),
+            func.src_alpha.to_gl(),
+            func.dst_alpha.to_gl(),
+        );
+    }
+
+    pub fn blend_func(&self) -> BlendFunc {
+        self.blend_func
+    }
+
+    pub unsafe fn set_blend_equation_separate(
+        &mut self,
+        color: BlendEquation,
+        alpha: BlendEquation,
+    ) {
+        debug::assert_context_current();
+        self.blend_equation = (color, alpha);
+        gl_trace!("glBlendEquationSeparate({:?}, {:?})", color, alpha);
+        gl::BlendEquationSeparate(color.to_gl(), alpha.to_gl());
+    }
+
+    pub fn blend_equation(&self) -> (BlendEquation, BlendEquation) {
+        self.blend_equation
+    }
+}
+
+impl RenderState {
+    // Runs the fragment shader for at least `min_fraction` of the samples of each pixel instead
+    // of once per pixel, which smooths high-frequency shader detail (alpha-tested edges, specular
+    // sparkle) under MSAA. At 1.0 this costs as many fragment shader invocations as there are
+    // samples, so with 4x MSAA fragment cost is roughly 4x. Passing 0.0 disables it.
+    pub unsafe fn set_sample_shading(&mut self, min_fraction: f32) {
+        debug::assert_context_current();
+        let min_fraction = min_fraction.clamp(0.0, 1.0);
+        self.min_sample_shading = min_fraction;
+        gl_trace!("glMinSampleShading({})", min_fraction);
+
+        if min_fraction > 0.0 {
+            gl::Enable(gl::SAMPLE_SHADING);
+            gl::MinSampleShading(min_fraction);
+        } else {
+            gl::Disable(gl::SAMPLE_SHADING);
+        }
+    }
+
+    pub fn sample_shading(&self) -> f32 {
+        self.min_sample_shading
+    }
+}
+
+impl RenderState {
+    // Clamps fragment depth to the depth range instead of clipping geometry at the near and far
+    // planes. Useful for shadow maps, where casters in front of the light's near plane must still
+    // write depth, and for skyboxes drawn at the far plane.
+    pub unsafe fn enable_depth_clamp(&mut self) {
+        debug::assert_context_current();
+        self.depth_clamp = true;
+        gl_trace!("glEnable(GL_DEPTH_CLAMP)");
+        gl::Enable(gl::DEPTH_CLAMP);
+    }
+
+    pub unsafe fn disable_depth_clamp(&mut self) {
+        debug::assert_context_current();
+        self.depth_clamp = false;
+        gl_trace!("glDisable(GL_DEPTH_CLAMP)");
+        gl::Disable(gl::DEPTH_CLAMP);
+    }
+
+    pub fn depth_clamp(&self) -> bool {
+        self.depth_clamp
+    }
+}
+
+impl RenderState {
+    // While enabled, an index equal to `restart_index` in an indexed draw ends the current strip,
+    // fan or loop and starts a new one, so several strips can share one index buffer and one draw
+    // call. Pick a value no real vertex uses; the maximum of the index type is the usual choice
+    // (0xFFFF for u16 indices, 0xFFFFFFFF for u32).
+    pub unsafe fn enable_primitive_restart(&mut self, restart_index: u32) {
+        debug::assert_context_current();
+        self.primitive_restart = Some(restart_index);
+        gl_trace!("glPrimitiveRestartIndex({:#x})", restart_index);
+        gl::Enable(gl::PRIMITIVE_RESTART);
+        gl::PrimitiveRestartIndex(restart_index);
+    }
+
+    pub unsafe fn disable_primitive_restart(&mut self) {
+        debug::assert_context_current();
+        self.primitive_restart = None;
+        gl_trace!("glDisable(GL_PRIMITIVE_RESTART)");
+        gl::Disable(gl::PRIMITIVE_RESTART);
+    }
+
+    pub fn primitive_restart(&self) -> Option<u32> {
+        self.primitive_restart
+    }
+}
+
+impl RenderState {
+    // Antialiases line primitives by having the rasterizer write partial coverage into alpha, so
+    // it also turns on blending with SrcAlpha / OneMinusSrcAlpha. How good the result looks is
+    // entirely up to the driver: some give nicely feathered lines, some ignore the hint, and it
+    // does nothing for triangles. A multisampled framebuffer is the reliable alternative.
+    pub unsafe fn enable_line_smooth(&mut self) {
+        debug::assert_context_current();
+        self.line_smooth = true;
+        gl_trace!("glEnable(GL_LINE_SMOOTH)");
+        gl::Enable(gl::LINE_SMOOTH);
+        gl::Hint(gl::LINE_SMOOTH_HINT, gl::NICEST);
+
+        self.set_blending(true);
+        self.set_blend_func(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+    }
+
+    // Leaves blending as it is, since other draws may rely on it.
+    pub unsafe fn disable_line_smooth(&mut self) {
+        debug::assert_context_current();
+        self.line_smooth = false;
+        gl_trace!("glDisable(GL_LINE_SMOOTH)");
+        gl::Disable(gl::LINE_SMOOTH);
+    }
+
+    pub fn line_smooth(&self) -> bool {
+        self.line_smooth
+    }
+}
+
+// Binding through these skips the GL call when the object is already bound. `Mesh::draw`,
+// `VertexArray::bind` and `Texture::bind` go through them. The cache only knows about binds made
+// through RenderState: after binding behind its back (e.g. ShaderProgram::apply, or creating a
+// vertex array or texture, which binds it) or deleting an object whose id may be reused, call
+// `invalidate_bindings`.
+impl RenderState {
+    pub unsafe fn use_program(&mut self, program: u32) {
+        debug::assert_context_current();
+        if self.bindings.program != Some(program) {
+            self.bindings.program = Some(program);
+            gl_trace!("glUseProgram({})", program);
+            gl::UseProgram(program);
+        }
+    }
+
+    pub unsafe fn bind_vertex_array(&mut self, vertex_array: u32) {
+        debug::assert_context_current();
+        if self.bindings.vertex_array != Some(vertex_array) {
+            self.bindings.vertex_array = Some(vertex_array);
+            gl_trace!("glBindVertexArray({})", vertex_array);
+            gl::BindVertexArray(vertex_array);
+        }
+    }
+
+    // Binds `texture` to GL_TEXTURE_2D on `unit`. When it has to, this leaves `unit` active.
+    pub unsafe fn bind_texture(&mut self, unit: u32, texture: u32) {
+        debug::assert_context_current();
+        if self.bindings.textures.get(&unit) == Some(&texture) {
+            return;
+        }
+
+        if self.bindings.active_texture_unit != Some(unit) {
+            self.bindings.active_texture_unit = Some(unit);
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+        }
+        self.bindings.textures.insert(unit, texture);
+        gl_trace!("glBindTexture(GL_TEXTURE_2D, {}) on unit {}", texture, unit);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+    }
+
+    pub fn invalidate_bindings(&mut self) {
+        self.bindings = BindingCache::default();
+    }
+}
+
+pub(crate) unsafe fn get_integer(pname: GLenum) -> GLint {
+    let mut value: GLint = 0;
+    gl::GetIntegerv(pname, &mut value);
+    value
+}
+
+pub unsafe fn current_vao() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::VERTEX_ARRAY_BINDING) as u32
+}
+
+pub unsafe fn current_program() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::CURRENT_PROGRAM) as u32
+}
+
+pub unsafe fn current_array_buffer() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::ARRAY_BUFFER_BINDING) as u32
+}
+
+// Element buffer bindings are VAO state, so this reflects the currently bound VAO.
+pub unsafe fn current_element_array_buffer() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::ELEMENT_ARRAY_BUFFER_BINDING) as u32
+}
+
+pub unsafe fn current_texture_2d() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::TEXTURE_BINDING_2D) as u32
+}
+
+pub unsafe fn current_active_texture_unit() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::ACTIVE_TEXTURE) as u32 - gl::TEXTURE0
+}
+
+pub unsafe fn current_framebuffer() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as u32
+}
+
+pub unsafe fn max_vertex_attribs() -> u32 {
+    debug::assert_context_current();
+    get_integer(gl::MAX_VERTEX_ATTRIBS) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn skips_redundant_program_binds() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            for _ in 0..3 {
+                render_state.use_program(1);
+            }
+            render_state.use_program(2);
+            render_state.use_program(2);
+            render_state.use_program(1);
+        }
+
+        assert_eq!(
+            spy.calls(),
+            ["glUseProgram(1)", "glUseProgram(2)", "glUseProgram(1)"]
+        );
+    }
+
+    #[test]
+    fn rebinds_after_invalidation() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            render_state.use_program(1);
+            render_state.invalidate_bindings();
+            render_state.use_program(1);
+        }
+
+        assert_eq!(spy.count("glUseProgram"), 2);
+    }
+
+    #[test]
+    fn skips_redundant_vertex_array_binds() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            render_state.bind_vertex_array(1);
+            render_state.bind_vertex_array(1);
+            render_state.bind_vertex_array(2);
+            render_state.bind_vertex_array(2);
+            render_state.bind_vertex_array(1);
+        }
+
+        assert_eq!(
+            spy.calls(),
+            [
+                "glBindVertexArray(1)",
+                "glBindVertexArray(2)",
+                "glBindVertexArray(1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn current_vao_follows_binds() {
+        let _spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            assert_eq!(current_vao(), 0);
+
+            render_state.bind_vertex_array(3);
+            assert_eq!(current_vao(), 3);
+
+            render_state.bind_vertex_array(0);
+            assert_eq!(current_vao(), 0);
+        }
+    }
+
+    #[test]
+    fn skips_redundant_texture_binds_per_unit() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            render_state.bind_texture(0, 7);
+            render_state.bind_texture(1, 8);
+            // Both units still hold their textures, switching between them binds nothing.
+            render_state.bind_texture(0, 7);
+            render_state.bind_texture(1, 8);
+            render_state.bind_texture(1, 9);
+        }
+
+        assert_eq!(
+            spy.calls(),
+            [
+                format!("glActiveTexture({:#x})", gl::TEXTURE0),
+                format!("glBindTexture({:#x}, 7)", gl::TEXTURE_2D),
+                format!("glActiveTexture({:#x})", gl::TEXTURE1),
+                format!("glBindTexture({:#x}, 8)", gl::TEXTURE_2D),
+                format!("glBindTexture({:#x}, 9)", gl::TEXTURE_2D),
+            ]
+        );
+    }
+
+    #[test]
+    fn clear_depth_round_trips() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        assert_eq!(render_state.clear_depth(), 1.0);
+
+        unsafe { render_state.set_clear_depth(0.25) };
+
+        assert_eq!(render_state.clear_depth(), 0.25);
+        assert_eq!(spy.calls(), ["glClearDepth(0.25)"]);
+    }
+
+    #[test]
+    fn reversed_z_sets_clear_depth() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAJOR_VERSION, 4);
+        spy.set_integer(gl::MINOR_VERSION, 5);
+        let mut render_state = RenderState::new();
+
+        assert!(unsafe { render_state.set_reversed_z(true) });
+        assert!(render_state.reversed_z());
+        assert_eq!(render_state.clear_depth(), 0.0);
+        assert_eq!(render_state.depth_func(), gl::GREATER);
+
+        assert!(unsafe { render_state.set_reversed_z(false) });
+        assert_eq!(render_state.clear_depth(), 1.0);
+        assert_eq!(render_state.depth_func(), gl::LESS);
+
+        assert_eq!(
+            spy.calls(),
+            [
+                format!(
+                    "glClipControl({:#x}, {:#x})",
+                    gl::LOWER_LEFT,
+                    gl::ZERO_TO_ONE
+                ),
+                format!("glDepthFunc({:#x})", gl::GREATER),
+                "glClearDepth(0)".to_string(),
+                format!(
+                    "glClipControl({:#x}, {:#x})",
+                    gl::LOWER_LEFT,
+                    gl::NEGATIVE_ONE_TO_ONE
+                ),
+                format!("glDepthFunc({:#x})", gl::LESS),
+                "glClearDepth(1)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn reversed_z_needs_clip_control() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAJOR_VERSION, 4);
+        spy.set_integer(gl::MINOR_VERSION, 2);
+        let mut render_state = RenderState::new();
+
+        assert!(!unsafe { render_state.set_reversed_z(true) });
+        assert!(!render_state.reversed_z());
+        assert_eq!(render_state.clear_depth(), 1.0);
+        assert!(spy.calls().is_empty());
+    }
+
+    #[test]
+    fn stores_separate_blend_state() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        let func = BlendFunc {
+            src_color: BlendFactor::One,
+            dst_color: BlendFactor::OneMinusSrcAlpha,
+            src_alpha: BlendFactor::One,
+            dst_alpha: BlendFactor::Zero,
+        };
+
+        unsafe {
+            render_state.set_blend_func_separate(func);
+            render_state.set_blend_equation_separate(BlendEquation::Add, BlendEquation::Max);
+        }
+
+        assert_eq!(render_state.blend_func(), func);
+        assert_eq!(
+            render_state.blend_equation(),
+            (BlendEquation::Add, BlendEquation::Max)
+        );
+        assert_eq!(
+            spy.calls(),
+            [
+                format!(
+                    "glBlendFuncSeparate({:#x}, {:#x}, {:#x}, {:#x})",
+                    gl::ONE,
+                    gl::ONE_MINUS_SRC_ALPHA,
+                    gl::ONE,
+                    gl::ZERO
+                ),
+                format!(
+                    "glBlendEquationSeparate({:#x}, {:#x})",
+                    gl::FUNC_ADD,
+                    gl::MAX
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_shading_enables_and_clamps() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe { render_state.set_sample_shading(2.0) };
+        assert_eq!(render_state.sample_shading(), 1.0);
+
+        unsafe { render_state.set_sample_shading(0.0) };
+        assert_eq!(render_state.sample_shading(), 0.0);
+
+        assert_eq!(
+            spy.calls(),
+            [
+                format!("glEnable({:#x})", gl::SAMPLE_SHADING),
+                "glMinSampleShading(1)".to_string(),
+                format!("glDisable({:#x})", gl::SAMPLE_SHADING),
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_clamp_toggles() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        assert!(!render_state.depth_clamp());
+
+        unsafe { render_state.enable_depth_clamp() };
+        assert!(render_state.depth_clamp());
+
+        unsafe { render_state.disable_depth_clamp() };
+        assert!(!render_state.depth_clamp());
+
+        assert_eq!(
+            spy.calls(),
+            [
+                format!("glEnable({:#x})", gl::DEPTH_CLAMP),
+                format!("glDisable({:#x})", gl::DEPTH_CLAMP),
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_mask_toggles() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        assert!(render_state.depth_mask());
+
+        unsafe { render_state.set_depth_mask(false) };
+        assert!(!render_state.depth_mask());
+
+        unsafe { render_state.set_depth_mask(true) };
+        assert!(render_state.depth_mask());
+
+        assert_eq!(spy.calls(), ["glDepthMask(0)", "glDepthMask(1)"]);
+    }
+}
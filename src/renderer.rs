@@ -0,0 +1,471 @@
+use std::{os::raw::c_void, path::Path};
+
+use gl::types::*;
+
+use crate::buffers::{Buffer, VertexArray};
+use crate::color::linear_to_srgb;
+use crate::debug;
+use crate::framebuffer::{Framebuffer, FramebufferError};
+use crate::mesh::Mesh;
+use crate::render_state::{get_integer, RenderState};
+use crate::shaders::ShaderProgram;
+use crate::texture::Texture;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    Triangles,
+    Lines,
+    Points,
+    TriangleStrip,
+    LineLoop,
+}
+
+impl DrawMode {
+    pub fn to_gl(self) -> GLenum {
+        match self {
+            DrawMode::Triangles => gl::TRIANGLES,
+            DrawMode::Lines => gl::LINES,
+            DrawMode::Points => gl::POINTS,
+            DrawMode::TriangleStrip => gl::TRIANGLE_STRIP,
+            DrawMode::LineLoop => gl::LINE_LOOP,
+        }
+    }
+}
+
+// Element types an index buffer can hold, so `draw_indexed` can pass the matching type to GL.
+pub trait IndexType {
+    const GL_TYPE: GLenum;
+}
+
+impl IndexType for u8 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl IndexType for u16 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl IndexType for u32 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_INT;
+}
+
+// The draws bind their vertex array through `render_state`, which skips the bind when it is
+// already bound.
+
+// Draws `count` vertices from the start of the vertex array's buffers.
+pub unsafe fn draw(
+    render_state: &mut RenderState,
+    vertex_array: &VertexArray,
+    mode: DrawMode,
+    count: i32,
+) {
+    debug::assert_context_current();
+    vertex_array.bind(render_state);
+    gl_trace!("glDrawArrays({:?}, 0, {})", mode, count);
+    gl::DrawArrays(mode.to_gl(), 0, count);
+}
+
+// Draws every index in `indices`. The index buffer is bound to the vertex array as a side effect,
+// which is where GL looks for it anyway.
+pub unsafe fn draw_indexed<I: IndexType>(
+    render_state: &mut RenderState,
+    vertex_array: &VertexArray,
+    mode: DrawMode,
+    indices: &Buffer<I>,
+) {
+    debug::assert_context_current();
+    vertex_array.bind(render_state);
+    indices.bind();
+    gl_trace!(
+        "glDrawElements({:?}, {}, {:#x})",
+        mode,
+        indices.len(),
+        I::GL_TYPE
+    );
+    gl::DrawElements(
+        mode.to_gl(),
+        indices.len() as i32,
+        I::GL_TYPE,
+        std::ptr::null(),
+    );
+}
+
+// `draw` and `draw_indexed` repeated `instance_count` times. Attributes with a divisor (see
+// `VertexArray::set_attribute_divisor`) advance per instance, and shaders can read gl_InstanceID.
+pub unsafe fn draw_instanced(
+    render_state: &mut RenderState,
+    vertex_array: &VertexArray,
+    mode: DrawMode,
+    count: i32,
+    instance_count: i32,
+) {
+    debug::assert_context_current();
+    vertex_array.bind(render_state);
+    gl_trace!(
+        "glDrawArraysInstanced({:?}, 0, {}, {} instances)",
+        mode,
+        count,
+        instance_count
+    );
+    gl::DrawArraysInstanced(mode.to_gl(), 0, count, instance_count);
+}
+
+pub unsafe fn draw_indexed_instanced<I: IndexType>(
+    render_state: &mut RenderState,
+    vertex_array: &VertexArray,
+    mode: DrawMode,
+    indices: &Buffer<I>,
+    instance_count: i32,
+) {
+    debug::assert_context_current();
+    vertex_array.bind(render_state);
+    indices.bind();
+    gl_trace!(
+        "glDrawElementsInstanced({:?}, {}, {:#x}, {} instances)",
+        mode,
+        indices.len(),
+        I::GL_TYPE,
+        instance_count
+    );
+    gl::DrawElementsInstanced(
+        mode.to_gl(),
+        indices.len() as i32,
+        I::GL_TYPE,
+        std::ptr::null(),
+        instance_count,
+    );
+}
+
+// Draws `mesh` with `program`, for multi-pass effects (depth prepass, shadows, the main pass) that
+// reuse one mesh with several programs. Switching programs leaves the vertex array bound, so
+// drawing the same mesh again only changes the program.
+pub unsafe fn draw_mesh_with<V>(
+    render_state: &mut RenderState,
+    mesh: &Mesh<V>,
+    program: &ShaderProgram,
+) {
+    render_state.use_program(program.id);
+    mesh.draw(render_state);
+}
+
+// Draws `count` vertices as patches of `vertices_per_patch` control points each, for programs
+// with tessellation stages (see `ShaderProgramBuilder::tess_control`). `count` should be a
+// multiple of `vertices_per_patch`, leftover vertices are ignored.
+pub unsafe fn draw_patches(
+    render_state: &mut RenderState,
+    vertex_array: &VertexArray,
+    vertices_per_patch: i32,
+    count: i32,
+) {
+    debug::assert_context_current();
+    debug_assert!(vertices_per_patch <= get_integer(gl::MAX_PATCH_VERTICES));
+    vertex_array.bind(render_state);
+
+    gl_trace!(
+        "glPatchParameteri(GL_PATCH_VERTICES, {})",
+        vertices_per_patch
+    );
+    gl::PatchParameteri(gl::PATCH_VERTICES, vertices_per_patch);
+    gl_trace!("glDrawArrays(GL_PATCHES, 0, {})", count);
+    gl::DrawArrays(gl::PATCHES, 0, count);
+}
+
+// Runs the bound compute program over `x` * `y` * `z` work groups. Results written to buffers or
+// images have to be made visible with gl::MemoryBarrier before they are read.
+pub unsafe fn dispatch_compute(x: u32, y: u32, z: u32) {
+    debug::assert_context_current();
+    gl_trace!("glDispatchCompute({}, {}, {})", x, y, z);
+    gl::DispatchCompute(x, y, z);
+}
+
+// Renders whatever `draw` draws into a new `width` x `height` texture and returns it. While `draw`
+// runs the texture's framebuffer is bound and the viewport covers it; the previous framebuffer and
+// viewport are restored afterwards. The framebuffer has a depth buffer too, which is dropped with
+// it.
+pub unsafe fn render_to_texture(
+    width: i32,
+    height: i32,
+    draw: impl FnOnce(&Framebuffer),
+) -> Result<Texture, FramebufferError> {
+    debug::assert_context_current();
+    let previous_framebuffer = get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as u32;
+    let mut viewport: [GLint; 4] = [0; 4];
+    gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+
+    let framebuffer = Framebuffer::with_color_texture(width, height);
+    if let Ok(framebuffer) = &framebuffer {
+        gl::Viewport(0, 0, width, height);
+        draw(framebuffer);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+    gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer);
+
+    Ok(framebuffer?
+        .into_color_texture()
+        .expect("render target without a color texture"))
+}
+
+// Saves what the viewport shows of the default framebuffer as an RGB PNG. The viewport is in
+// framebuffer pixels, which outnumber window coordinates on HiDPI displays, and `App` keeps it
+// covering the whole framebuffer. Call before swapping buffers, the back buffer is what gets
+// read. The read framebuffer binding and pack alignment are restored afterwards.
+//
+// With GL_FRAMEBUFFER_SRGB enabled, reading an sRGB framebuffer converts its pixels to linear, and
+// saving those as they are would give a darker image than the window shows. They are read as
+// floats and encoded back to sRGB then, which keeps the dark tones from banding.
+pub unsafe fn capture_screenshot(path: impl AsRef<Path>) -> image::ImageResult<()> {
+    debug::assert_context_current();
+    let mut viewport: [GLint; 4] = [0; 4];
+    gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    let [x, y, width, height] = viewport;
+    let (width, height) = (width.max(0) as u32, height.max(0) as u32);
+
+    let previous_framebuffer = get_integer(gl::READ_FRAMEBUFFER_BINDING) as u32;
+    let previous_alignment = get_integer(gl::PACK_ALIGNMENT);
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+
+    let mut encoding: GLint = 0;
+    gl::GetFramebufferAttachmentParameteriv(
+        gl::READ_FRAMEBUFFER,
+        gl::BACK_LEFT,
+        gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+        &mut encoding,
+    );
+    let linearized =
+        encoding as GLenum == gl::SRGB && gl::IsEnabled(gl::FRAMEBUFFER_SRGB) == gl::TRUE;
+
+    // RGB rows aren't 4-byte aligned in general.
+    let size = (width * height * 3) as usize;
+    gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+    gl_trace!(
+        "glReadPixels({}, {}, {}, {}, GL_RGB), linearized {}",
+        x,
+        y,
+        width,
+        height,
+        linearized
+    );
+    let pixels = if linearized {
+        let mut linear: Vec<f32> = vec![0.0; size];
+        gl::ReadPixels(
+            x,
+            y,
+            width as GLsizei,
+            height as GLsizei,
+            gl::RGB,
+            gl::FLOAT,
+            linear.as_mut_ptr() as *mut c_void,
+        );
+        linear
+            .into_iter()
+            .map(|value| (linear_to_srgb(value) * 255.0).round() as u8)
+            .collect()
+    } else {
+        let mut pixels: Vec<u8> = vec![0; size];
+        gl::ReadPixels(
+            x,
+            y,
+            width as GLsizei,
+            height as GLsizei,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut c_void,
+        );
+        pixels
+    };
+
+    gl::PixelStorei(gl::PACK_ALIGNMENT, previous_alignment);
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_framebuffer);
+
+    // GL returns the bottom row first, images are stored top row first.
+    let mut image = image::RgbImage::from_raw(width, height, pixels).unwrap();
+    image::imageops::flip_vertical_in_place(&mut image);
+    image.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::BufferUsage;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn instanced_draws_bind_and_pass_the_index_type() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            let vertex_array = VertexArray::new().unwrap();
+            let indices = Buffer::<u16>::new(gl::ELEMENT_ARRAY_BUFFER).unwrap();
+            indices.set_data(&[0, 1, 2, 2, 3, 0], BufferUsage::StaticDraw);
+
+            draw_instanced(&mut render_state, &vertex_array, DrawMode::Points, 4, 16);
+            draw_indexed_instanced(
+                &mut render_state,
+                &vertex_array,
+                DrawMode::Triangles,
+                &indices,
+                16,
+            );
+
+            let calls = spy.calls();
+            let draws: Vec<_> = calls
+                .iter()
+                .filter(|call| call.starts_with("glDraw"))
+                .collect();
+            assert_eq!(
+                draws,
+                [
+                    &format!("glDrawArraysInstanced({:#x}, 0, 4, 16)", gl::POINTS),
+                    &format!(
+                        "glDrawElementsInstanced({:#x}, 6, {:#x}, 16)",
+                        gl::TRIANGLES,
+                        gl::UNSIGNED_SHORT
+                    ),
+                ]
+            );
+            let vertex_array_binds = format!("glBindVertexArray({})", vertex_array.id);
+            assert_eq!(
+                calls
+                    .iter()
+                    .filter(|call| **call == vertex_array_binds)
+                    .count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn drawing_a_mesh_with_two_programs_binds_it_once() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::MAX_VERTEX_ATTRIBS, 16);
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            let mesh = Mesh::new(&[[0.0f32; 3]; 3], None, &[(0, 3, gl::FLOAT)]).unwrap();
+            let depth_prepass = ShaderProgram::from_raw(10);
+            let main_pass = ShaderProgram::from_raw(11);
+            let setup_calls = spy.calls().len();
+
+            draw_mesh_with(&mut render_state, &mesh, &depth_prepass);
+            draw_mesh_with(&mut render_state, &mesh, &main_pass);
+
+            let triangles = format!("glDrawArrays({:#x}, 0, 3)", gl::TRIANGLES);
+            assert_eq!(
+                spy.calls()[setup_calls..],
+                [
+                    "glUseProgram(10)".to_string(),
+                    format!("glBindVertexArray({})", mesh.vertex_array().id),
+                    triangles.clone(),
+                    "glUseProgram(11)".to_string(),
+                    triangles,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn renders_into_a_texture_and_restores_the_target() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::DRAW_FRAMEBUFFER_BINDING, 3);
+        spy.set_integers(gl::VIEWPORT, &[0, 0, 800, 600]);
+        let mut target = 0;
+
+        let texture = unsafe {
+            render_to_texture(64, 32, |framebuffer| {
+                target = framebuffer.id();
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            })
+            .unwrap()
+        };
+
+        assert_eq!((texture.width(), texture.height()), (64, 32));
+        let calls = spy.calls();
+        assert!(calls.contains(&format!(
+            "glFramebufferTexture2D({:#x}, {:#x}, {:#x}, {}, 0)",
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture.id()
+        )));
+        assert!(calls.contains(&format!(
+            "glBindFramebuffer({:#x}, {})",
+            gl::FRAMEBUFFER,
+            target
+        )));
+
+        let draw = calls
+            .iter()
+            .position(|call| call.starts_with("glDrawArrays"));
+        let draw = draw.unwrap();
+        assert_eq!(calls[draw - 1], "glViewport(0, 0, 64, 32)");
+        assert_eq!(
+            calls[draw + 1..draw + 4],
+            [
+                "glViewport(0, 0, 800, 600)".to_string(),
+                format!("glBindFramebuffer({:#x}, 3)", gl::FRAMEBUFFER),
+                format!("glDeleteFramebuffers([{}])", target),
+            ]
+        );
+        // The texture outlives its framebuffer.
+        assert_eq!(spy.count("glDeleteTextures"), 0);
+    }
+
+    #[test]
+    fn screenshot_reads_the_viewport_and_restores_state() {
+        let spy = GlSpy::new();
+        spy.set_integers(gl::VIEWPORT, &[0, 0, 5, 3]);
+        spy.set_integer(gl::PACK_ALIGNMENT, 4);
+        spy.set_integer(gl::READ_FRAMEBUFFER_BINDING, 2);
+        let path =
+            std::env::temp_dir().join(format!("opengl_rust_screenshot_{}.png", std::process::id()));
+
+        unsafe { capture_screenshot(&path).unwrap() };
+
+        let image = image::open(&path).unwrap().into_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (5, 3));
+        assert_eq!(image.get_pixel(4, 0).0, [255; 3]);
+
+        let calls = spy.calls();
+        let read = format!(
+            "glReadPixels(0, 0, 5, 3, {:#x}, {:#x})",
+            gl::RGB,
+            gl::UNSIGNED_BYTE
+        );
+        let read_index = calls.iter().position(|call| *call == read).unwrap();
+        assert_eq!(
+            calls[read_index + 1..],
+            [
+                format!("glPixelStorei({:#x}, 4)", gl::PACK_ALIGNMENT),
+                format!("glBindFramebuffer({:#x}, 2)", gl::READ_FRAMEBUFFER),
+            ]
+        );
+    }
+
+    // Saves a three pixel gradient, linear 0, 0.5 and 1, and returns the middle pixel's red.
+    fn screenshot_midtone(spy: &GlSpy) -> u8 {
+        spy.set_integers(gl::VIEWPORT, &[0, 0, 3, 1]);
+        let path =
+            std::env::temp_dir().join(format!("opengl_rust_midtone_{}.png", std::process::id()));
+
+        unsafe { capture_screenshot(&path).unwrap() };
+
+        let image = image::open(&path).unwrap().into_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        image.get_pixel(1, 0).0[0]
+    }
+
+    #[test]
+    fn screenshot_encodes_linearized_reads_as_srgb() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING, gl::SRGB as GLint);
+
+        // Without GL_FRAMEBUFFER_SRGB the stored bytes come back as they are.
+        assert_eq!(screenshot_midtone(&spy), 128);
+
+        unsafe { gl::Enable(gl::FRAMEBUFFER_SRGB) };
+        assert_eq!(screenshot_midtone(&spy), 188);
+        assert_eq!(spy.count("glReadPixels"), 2);
+    }
+}
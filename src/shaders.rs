@@ -1,7 +1,20 @@
 use gl::types::*;
-use std::{ffi::CString, string::FromUtf8Error};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    ffi::{CStr, CString},
+    fs,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+    time::SystemTime,
+};
 use thiserror::Error;
 
+use crate::debug;
+use crate::render_state::{current_program, get_integer};
+
 #[derive(Debug, Error)]
 pub enum ShaderError {
     #[error("Error while compiling shader: {0}")]
@@ -12,21 +25,76 @@ pub enum ShaderError {
     Utf8Error(#[from] FromUtf8Error),
     #[error{"{0}"}]
     NulError(#[from] std::ffi::NulError),
+    #[error("Uniform not found: {0}")]
+    UniformNotFound(String),
+    #[error("Shader program has no {0} shader, vertex and fragment shaders are both required")]
+    MissingStage(&'static str),
+    #[error(
+        "Tessellation shaders need GL 4.0 or GL_ARB_tessellation_shader, the context is GL {0}.{1}"
+    )]
+    TessellationUnsupported(GLint, GLint),
+    #[error("Error while validating program: {0}")]
+    ValidationError(String),
+    #[error("Uniform block not found: {0}")]
+    UniformBlockNotFound(String),
+    #[error("Uniform block {0} has no conventional binding point")]
+    NoDefaultBinding(String),
+    #[error{"{0}"}]
+    Io(#[from] std::io::Error),
+    #[error("Failed to resolve shader include: {0}")]
+    Include(String),
+    // Carries the requested shader type, or gl::PROGRAM for programs.
+    #[error("Failed to create GL object of type {0:#x}")]
+    CreationFailed(GLenum),
+}
+
+// Reads the info log of a shader or program object, given the matching glGet*iv and
+// glGet*InfoLog functions.
+unsafe fn read_info_log(
+    id: GLuint,
+    get_parameter: unsafe fn(GLuint, GLenum, *mut GLint),
+    get_info_log: unsafe fn(GLuint, GLsizei, *mut GLsizei, *mut GLchar),
+) -> Result<String, ShaderError> {
+    let mut log_size: GLint = 0;
+    get_parameter(id, gl::INFO_LOG_LENGTH, &mut log_size);
+
+    let mut log: Vec<u8> = vec![0; log_size.max(0) as usize];
+    let mut written: GLsizei = 0;
+    get_info_log(id, log_size, &mut written, log.as_mut_ptr() as *mut GLchar);
+    log.truncate(written.max(0) as usize);
+
+    // Some drivers include the terminating NUL in the length they report.
+    while log.last() == Some(&0) {
+        log.pop();
+    }
+
+    Ok(String::from_utf8(log)?)
 }
 
+// Like the buffer wrappers, shaders and programs are tied to the context's thread and are
+// neither Send nor Sync.
 pub struct Shader {
     pub id: u32,
+    _not_send: PhantomData<*const ()>,
 }
 
 impl Shader {
     pub unsafe fn new(shader_source: &str, shader_type: GLenum) -> Result<Self, ShaderError> {
+        debug::assert_context_current();
+        let shader_source = CString::new(shader_source)?;
+        let id = gl::CreateShader(shader_type);
+        if id == 0 {
+            return Err(ShaderError::CreationFailed(shader_type));
+        }
+
         let shader = Self {
-            id: gl::CreateShader(shader_type),
+            id,
+            _not_send: PhantomData,
         };
-        let shader_source = CString::new(shader_source).unwrap();
 
         gl::ShaderSource(shader.id, 1, &shader_source.as_ptr(), std::ptr::null());
         gl::CompileShader(shader.id);
+        gl_trace!("glCompileShader({}) type {:#x}", shader.id, shader_type);
 
         // check for shader compilation errors
         let mut success: GLint = 0;
@@ -35,25 +103,128 @@ impl Shader {
         if success == 1 {
             Ok(shader)
         } else {
-            let mut error_log_size: GLint = 0;
-            gl::GetShaderiv(shader.id, gl::INFO_LOG_LENGTH, &mut error_log_size);
-            let mut error_log: Vec<u8> = Vec::with_capacity(error_log_size as usize);
-            gl::GetShaderInfoLog(
-                shader.id,
-                error_log_size,
-                &mut error_log_size,
-                error_log.as_mut_ptr() as *mut _,
-            );
-
-            error_log.set_len(error_log_size as usize);
-            let log = String::from_utf8(error_log).unwrap();
+            let log = read_info_log(shader.id, gl::GetShaderiv, gl::GetShaderInfoLog)?;
             Err(ShaderError::CompilationError(log))
         }
     }
 }
 
+impl Shader {
+    // `#include "file"` lines in the source are replaced by the contents of that file, relative
+    // to the including file's directory.
+    pub unsafe fn from_file(
+        path: impl AsRef<Path>,
+        shader_type: GLenum,
+    ) -> Result<Self, ShaderError> {
+        let shader_source = read_source(path.as_ref())?;
+        Self::new(&shader_source, shader_type)
+    }
+}
+
+// Reads a shader file with its includes resolved.
+fn read_source(path: &Path) -> Result<String, ShaderError> {
+    read_source_with_includes(path, &mut Vec::new())
+}
+
+// Same as `read_source`, also adding each included file to `includes` for hot reloading.
+fn read_source_with_includes(
+    path: &Path,
+    includes: &mut Vec<IncludedFile>,
+) -> Result<String, ShaderError> {
+    let source = fs::read_to_string(path)?;
+    let mut include_stack = vec![path.canonicalize()?];
+    resolve_includes(&source, path, &mut include_stack, includes)
+}
+
+// Inlines `#include "file"` lines recursively. `include_stack` holds the canonical paths of the
+// files currently being expanded, so a file that ends up including itself is reported instead of
+// recursing forever.
+fn resolve_includes(
+    source: &str,
+    path: &Path,
+    include_stack: &mut Vec<PathBuf>,
+    includes: &mut Vec<IncludedFile>,
+) -> Result<String, ShaderError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut output = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        match include_name(line) {
+            Some(name) => {
+                let include_path = directory.join(name);
+                let canonical_path = include_path.canonicalize().map_err(|err| {
+                    ShaderError::Include(format!(
+                        "{} (included from {}): {}",
+                        include_path.display(),
+                        path.display(),
+                        err
+                    ))
+                })?;
+
+                if include_stack.contains(&canonical_path) {
+                    return Err(ShaderError::Include(format!(
+                        "{} includes itself through {}",
+                        include_path.display(),
+                        path.display()
+                    )));
+                }
+
+                if !includes
+                    .iter()
+                    .any(|include| include.path == canonical_path)
+                {
+                    includes.push(IncludedFile {
+                        path: canonical_path.clone(),
+                        modified: modified_time(&canonical_path),
+                    });
+                }
+
+                let included_source = fs::read_to_string(&canonical_path)?;
+                include_stack.push(canonical_path);
+                output.push_str(&resolve_includes(
+                    &included_source,
+                    &include_path,
+                    include_stack,
+                    includes,
+                )?);
+                include_stack.pop();
+            }
+            None => output.push_str(line),
+        }
+
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+// The quoted file name of an `#include "file"` line.
+fn include_name(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("#include")?.trim();
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+impl Shader {
+    // Wraps a shader object created elsewhere, which is then deleted on drop unless handed back
+    // with `into_raw`.
+    pub unsafe fn from_raw(id: u32) -> Self {
+        Self {
+            id,
+            _not_send: PhantomData,
+        }
+    }
+
+    // Releases ownership of the GL shader object without deleting it.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        std::mem::forget(self);
+        id
+    }
+}
+
 impl Drop for Shader {
     fn drop(&mut self) {
+        gl_trace!("glDeleteShader({})", self.id);
         unsafe {
             gl::DeleteShader(self.id);
         }
@@ -62,19 +233,80 @@ impl Drop for Shader {
 
 pub struct ShaderProgram {
     pub id: u32,
+    // Uniform locations by name, including -1 for names the program doesn't have. Only valid
+    // for the program as linked; every new program, reloaded ones included, starts empty.
+    locations: RefCell<HashMap<String, GLint>>,
+    // The files a program built by `from_files` was compiled from, empty otherwise.
+    sources: RefCell<Vec<SourceFile>>,
+    _not_send: PhantomData<*const ()>,
+}
+
+struct SourceFile {
+    path: PathBuf,
+    shader_type: GLenum,
+    // Last modification time seen by `from_files` or `poll_source_changes`.
+    modified: Option<SystemTime>,
+    // Every file the source pulled in with `#include`, directly or through other includes.
+    includes: Vec<IncludedFile>,
+}
+
+struct IncludedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Stores the file's current modification time in `last_seen`, returning true if it changed.
+fn update_modified_time(path: &Path, last_seen: &mut Option<SystemTime>) -> bool {
+    let modified = modified_time(path);
+    let changed = modified != *last_seen;
+    *last_seen = modified;
+    changed
 }
 
 impl ShaderProgram {
     pub unsafe fn new(shaders: &[Shader]) -> Result<Self, ShaderError> {
+        Self::link(shaders, false)
+    }
+
+    // `retrievable` asks the driver to keep the linked binary around for glGetProgramBinary.
+    unsafe fn link(shaders: &[Shader], retrievable: bool) -> Result<Self, ShaderError> {
+        debug::assert_context_current();
+        let id = gl::CreateProgram();
+        if id == 0 {
+            return Err(ShaderError::CreationFailed(gl::PROGRAM));
+        }
+
         let program = Self {
-            id: gl::CreateProgram(),
+            id,
+            locations: RefCell::new(HashMap::new()),
+            sources: RefCell::new(Vec::new()),
+            _not_send: PhantomData,
         };
 
         for shader in shaders {
             gl::AttachShader(program.id, shader.id);
         }
 
+        if retrievable {
+            gl::ProgramParameteri(
+                program.id,
+                gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                gl::TRUE as GLint,
+            );
+        }
+
         gl::LinkProgram(program.id);
+        gl_trace!(
+            "glLinkProgram({}) with {} shaders",
+            program.id,
+            shaders.len()
+        );
 
         let mut sucess: i32 = 0;
         gl::GetProgramiv(program.id, gl::LINK_STATUS, &mut sucess);
@@ -82,33 +314,854 @@ impl ShaderProgram {
         if sucess == 1 {
             Ok(program)
         } else {
-            let mut error_log_size: i32 = 0;
-            gl::GetProgramiv(program.id, gl::INFO_LOG_LENGTH, &mut error_log_size);
-            let mut error_log: Vec<u8> = Vec::with_capacity(error_log_size as usize);
-            gl::GetProgramInfoLog(
-                program.id,
-                error_log_size,
-                &mut error_log_size,
-                error_log.as_mut_ptr() as *mut _,
+            let log = read_info_log(program.id, gl::GetProgramiv, gl::GetProgramInfoLog)?;
+            Err(ShaderError::LinkingError(log))
+        }
+    }
+}
+
+impl ShaderProgram {
+    pub unsafe fn from_files(
+        vertex_path: impl AsRef<Path>,
+        fragment_path: impl AsRef<Path>,
+    ) -> Result<Self, ShaderError> {
+        Self::from_source_files(
+            &[
+                (vertex_path.as_ref(), gl::VERTEX_SHADER),
+                (fragment_path.as_ref(), gl::FRAGMENT_SHADER),
+            ],
+            None,
+        )
+    }
+
+    unsafe fn from_source_files(
+        files: &[(&Path, GLenum)],
+        binary_cache: Option<&Path>,
+    ) -> Result<Self, ShaderError> {
+        // Modification times are taken before reading, so an edit made while compiling still
+        // shows up as a change afterwards.
+        let mut sources = Vec::with_capacity(files.len());
+        let mut texts = Vec::with_capacity(files.len());
+        for (path, shader_type) in files {
+            let modified = modified_time(path);
+            let mut includes = Vec::new();
+            texts.push(read_source_with_includes(path, &mut includes)?);
+            sources.push(SourceFile {
+                path: path.to_path_buf(),
+                shader_type: *shader_type,
+                modified,
+                includes,
+            });
+        }
+
+        let program = match binary_cache {
+            Some(cache_directory) => Self::from_binary_cache(files, &texts, cache_directory)?,
+            None => {
+                let shaders = files
+                    .iter()
+                    .zip(&texts)
+                    .map(|((_, shader_type), text)| Shader::new(text, *shader_type))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::new(&shaders)?
+            }
+        };
+        *program.sources.borrow_mut() = sources;
+
+        Ok(program)
+    }
+}
+
+// Hot reloading for programs built by `from_files`.
+impl ShaderProgram {
+    // Returns true if any source file, or any file it includes, was modified (or appeared or
+    // disappeared) since the last call, or since the program was built. Each change is only
+    // reported once, so a reload that fails to compile isn't retried until a file changes again.
+    pub fn poll_source_changes(&self) -> bool {
+        let mut changed = false;
+
+        for source in self.sources.borrow_mut().iter_mut() {
+            changed |= update_modified_time(&source.path, &mut source.modified);
+            for include in &mut source.includes {
+                changed |= update_modified_time(&include.path, &mut include.modified);
+            }
+        }
+
+        changed
+    }
+
+    // Builds a new program from the same source files. The current program is left untouched,
+    // so callers can keep using it if compilation fails.
+    pub unsafe fn reload(&self) -> Result<Self, ShaderError> {
+        let sources = self.sources.borrow();
+        let files: Vec<(&Path, GLenum)> = sources
+            .iter()
+            .map(|source| (source.path.as_path(), source.shader_type))
+            .collect();
+
+        Self::from_source_files(&files, None)
+    }
+}
+
+// Collects the source file of each stage. Setting a stage again replaces its file.
+pub struct ShaderProgramBuilder {
+    stages: Vec<(PathBuf, GLenum)>,
+    binary_cache: Option<PathBuf>,
+}
+
+impl ShaderProgram {
+    pub fn builder() -> ShaderProgramBuilder {
+        ShaderProgramBuilder {
+            stages: Vec::new(),
+            binary_cache: None,
+        }
+    }
+}
+
+impl ShaderProgramBuilder {
+    pub fn vertex(self, path: impl AsRef<Path>) -> Self {
+        self.stage(path.as_ref(), gl::VERTEX_SHADER)
+    }
+
+    // Runs on whole primitives between the vertex and fragment stages, and can emit any number of
+    // new ones.
+    pub fn geometry(self, path: impl AsRef<Path>) -> Self {
+        self.stage(path.as_ref(), gl::GEOMETRY_SHADER)
+    }
+
+    // Tessellation runs between the vertex and geometry stages on patches drawn with
+    // `renderer::draw_patches`. The control stage is optional, without it the tessellation levels
+    // are the defaults set with glPatchParameterfv.
+    pub fn tess_control(self, path: impl AsRef<Path>) -> Self {
+        self.stage(path.as_ref(), gl::TESS_CONTROL_SHADER)
+    }
+
+    pub fn tess_evaluation(self, path: impl AsRef<Path>) -> Self {
+        self.stage(path.as_ref(), gl::TESS_EVALUATION_SHADER)
+    }
+
+    pub fn fragment(self, path: impl AsRef<Path>) -> Self {
+        self.stage(path.as_ref(), gl::FRAGMENT_SHADER)
+    }
+
+    fn stage(mut self, path: &Path, shader_type: GLenum) -> Self {
+        self.stages
+            .retain(|(_, stage_type)| *stage_type != shader_type);
+        self.stages.push((path.to_path_buf(), shader_type));
+        self
+    }
+
+    // Stores the linked program binary in `directory` and loads it from there the next time the
+    // same sources are built, skipping compilation. Binaries the driver rejects (after a driver
+    // update, say) are rebuilt from source and overwritten. Reloads always compile from source.
+    pub fn with_binary_cache(mut self, directory: impl AsRef<Path>) -> Self {
+        self.binary_cache = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    // Fails without compiling anything if the vertex or fragment shader is missing, or if there
+    // are tessellation stages the context can't run.
+    pub unsafe fn build(self) -> Result<ShaderProgram, ShaderError> {
+        for (shader_type, name) in [
+            (gl::VERTEX_SHADER, "vertex"),
+            (gl::FRAGMENT_SHADER, "fragment"),
+        ] {
+            if !self.has_stage(shader_type) {
+                return Err(ShaderError::MissingStage(name));
+            }
+        }
+
+        if self.has_stage(gl::TESS_CONTROL_SHADER) || self.has_stage(gl::TESS_EVALUATION_SHADER) {
+            debug::assert_context_current();
+            let version = (
+                get_integer(gl::MAJOR_VERSION),
+                get_integer(gl::MINOR_VERSION),
             );
+            if version.0 < 4 && !debug::has_extension("GL_ARB_tessellation_shader") {
+                return Err(ShaderError::TessellationUnsupported(version.0, version.1));
+            }
+        }
 
-            error_log.set_len(error_log_size as usize);
-            let log = String::from_utf8(error_log)?;
-            Err(ShaderError::LinkingError(log))
+        let files: Vec<(&Path, GLenum)> = self
+            .stages
+            .iter()
+            .map(|(path, shader_type)| (path.as_path(), *shader_type))
+            .collect();
+
+        ShaderProgram::from_source_files(&files, self.binary_cache.as_deref())
+    }
+
+    fn has_stage(&self, shader_type: GLenum) -> bool {
+        self.stages
+            .iter()
+            .any(|(_, stage_type)| *stage_type == shader_type)
+    }
+}
+
+// Program binary caching. A cache file holds the binary format as a little-endian u32 followed by
+// the binary itself, and is named after a hash of the preprocessed sources and the driver, so
+// editing a shader or an include just misses the cache.
+impl ShaderProgram {
+    // `sources` are the files' sources with their includes resolved.
+    unsafe fn from_binary_cache(
+        files: &[(&Path, GLenum)],
+        sources: &[String],
+        cache_directory: &Path,
+    ) -> Result<Self, ShaderError> {
+        let mut hasher = DefaultHasher::new();
+        for ((_, shader_type), source) in files.iter().zip(sources) {
+            shader_type.hash(&mut hasher);
+            source.hash(&mut hasher);
+        }
+        for name in [gl::VENDOR, gl::RENDERER, gl::VERSION] {
+            gl_string(name).hash(&mut hasher);
+        }
+        let cache_path = cache_directory.join(format!("{:016x}.bin", hasher.finish()));
+
+        if let Some(program) = Self::load_binary(&cache_path) {
+            gl_trace!("program binary cache hit: {}", cache_path.display());
+            return Ok(program);
+        }
+
+        let shaders = files
+            .iter()
+            .zip(sources)
+            .map(|((_, shader_type), source)| Shader::new(source, *shader_type))
+            .collect::<Result<Vec<_>, _>>()?;
+        let program = Self::link(&shaders, true)?;
+
+        // The program works either way, a cache that can't be written only costs the next startup.
+        if let Err(err) = program.save_binary(&cache_path) {
+            eprintln!(
+                "Failed to write program binary cache {}: {}",
+                cache_path.display(),
+                err
+            );
         }
+
+        Ok(program)
+    }
+
+    // None if the file is missing or malformed, or the driver doesn't accept the binary.
+    unsafe fn load_binary(path: &Path) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        if data.len() < 4 {
+            return None;
+        }
+        let (format, binary) = data.split_at(4);
+        let format = GLenum::from_le_bytes(format.try_into().unwrap());
+
+        debug::assert_context_current();
+        let program = Self::from_raw(gl::CreateProgram());
+        if program.id == 0 {
+            return None;
+        }
+
+        gl::ProgramBinary(
+            program.id,
+            format,
+            binary.as_ptr() as *const _,
+            binary.len() as GLsizei,
+        );
+
+        let mut success: GLint = 0;
+        gl::GetProgramiv(program.id, gl::LINK_STATUS, &mut success);
+        (success == 1).then_some(program)
+    }
+
+    unsafe fn save_binary(&self, path: &Path) -> std::io::Result<()> {
+        let (format, binary) = self.binary();
+        if binary.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut data = Vec::with_capacity(4 + binary.len());
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&binary);
+        fs::write(path, data)
+    }
+}
+
+// glGetString as a Rust string, empty if GL returns null.
+unsafe fn gl_string(name: GLenum) -> String {
+    let string = gl::GetString(name);
+    if string.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(string as *const _)
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl ShaderProgram {
+    // Format and size in bytes of the driver's binary for this linked program, for diagnosing
+    // program binary caching. Returns (0, 0) when the driver advertises no binary formats.
+    pub unsafe fn binary_info(&self) -> (GLenum, usize) {
+        let (format, binary) = self.binary();
+        (format, binary.len())
+    }
+
+    // The driver's binary for this program and its format, or (0, empty) if there is none.
+    unsafe fn binary(&self) -> (GLenum, Vec<u8>) {
+        debug::assert_context_current();
+        let mut format_count: GLint = 0;
+        gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_count);
+
+        let mut length: GLint = 0;
+        gl::GetProgramiv(self.id, gl::PROGRAM_BINARY_LENGTH, &mut length);
+
+        if format_count == 0 || length <= 0 {
+            return (0, Vec::new());
+        }
+
+        // The format is only reported alongside the binary itself.
+        let mut binary: Vec<u8> = vec![0; length as usize];
+        let mut format: GLenum = 0;
+        gl::GetProgramBinary(
+            self.id,
+            length,
+            &mut length,
+            &mut format,
+            binary.as_mut_ptr() as *mut _,
+        );
+        binary.truncate(length.max(0) as usize);
+
+        (format, binary)
     }
 }
 
 impl ShaderProgram {
     pub unsafe fn apply(&self) {
+        debug::assert_context_current();
+        gl_trace!("glUseProgram({})", self.id);
         gl::UseProgram(self.id);
     }
 }
 
+impl ShaderProgram {
+    // Checks whether the program can run with the current GL state (bound vertex array, texture
+    // units, sampler uniforms...), which linking alone doesn't. Slow, only meant for debugging.
+    pub unsafe fn validate(&self) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        gl_trace!("glValidateProgram({})", self.id);
+        gl::ValidateProgram(self.id);
+
+        let mut success: GLint = 0;
+        gl::GetProgramiv(self.id, gl::VALIDATE_STATUS, &mut success);
+
+        if success == 1 {
+            Ok(())
+        } else {
+            let log = read_info_log(self.id, gl::GetProgramiv, gl::GetProgramInfoLog)?;
+            Err(ShaderError::ValidationError(log))
+        }
+    }
+}
+
+// Uniform setters apply to the current program, so this one has to be bound first, normally with
+// `RenderState::use_program` so the binding cache stays in sync. Debug builds check that it is.
+// A uniform that doesn't exist or was optimized out of the program returns
+// `ShaderError::UniformNotFound`.
+impl ShaderProgram {
+    unsafe fn location(&self, name: &str) -> Result<GLint, ShaderError> {
+        if let Some(location) = self.locations.borrow().get(name) {
+            return Ok(*location);
+        }
+
+        let uniform_name = CString::new(name)?;
+        let location = gl::GetUniformLocation(self.id, uniform_name.as_ptr());
+        self.locations
+            .borrow_mut()
+            .insert(name.to_string(), location);
+
+        Ok(location)
+    }
+
+    // Forgets every cached location, so the next setter queries GL again. Needed after relinking
+    // this program object in place (e.g. glLinkProgram on it from raw GL), which can move or
+    // drop uniforms.
+    pub fn clear_uniform_cache(&self) {
+        self.locations.borrow_mut().clear();
+    }
+
+    unsafe fn uniform_location(&self, name: &str) -> Result<GLint, ShaderError> {
+        let location = self.location(name)?;
+
+        if location == -1 {
+            return Err(ShaderError::UniformNotFound(name.to_string()));
+        }
+
+        if cfg!(debug_assertions) {
+            let current = current_program();
+            assert_eq!(
+                current, self.id,
+                "setting uniform {} of program {} while program {} is bound",
+                name, self.id, current
+            );
+        }
+
+        Ok(location)
+    }
+
+    pub unsafe fn set_uniform_f32(&self, name: &str, value: f32) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::Uniform1f(location, value);
+        Ok(())
+    }
+
+    pub unsafe fn set_uniform_i32(&self, name: &str, value: i32) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::Uniform1i(location, value);
+        Ok(())
+    }
+
+    pub unsafe fn set_uniform_vec2(&self, name: &str, value: [f32; 2]) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::Uniform2fv(location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_uniform_vec3(&self, name: &str, value: [f32; 3]) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::Uniform3fv(location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    pub unsafe fn set_uniform_vec4(&self, name: &str, value: [f32; 4]) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::Uniform4fv(location, 1, value.as_ptr());
+        Ok(())
+    }
+
+    // Column-major like `set_uniform_mat4`. Mostly for normal matrices, see `math::normal_matrix`.
+    pub unsafe fn set_uniform_mat3(
+        &self,
+        name: &str,
+        value: &[[f32; 3]; 3],
+    ) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::UniformMatrix3fv(location, 1, gl::FALSE, value.as_ptr() as *const GLfloat);
+        Ok(())
+    }
+
+    // Column-major, each inner array is one column, as GLSL expects.
+    pub unsafe fn set_uniform_mat4(
+        &self,
+        name: &str,
+        value: &[[f32; 4]; 4],
+    ) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let location = self.uniform_location(name)?;
+        gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr() as *const GLfloat);
+        Ok(())
+    }
+}
+
+// Uniform buffer binding points by kind of data, so shaders and the code filling the buffers
+// agree on them. `bind_default_uniform_block` binds a block named after its kind to its point;
+// GLSL 4.2 shaders can instead declare it, e.g. `layout(std140, binding = 0) uniform Frame`.
+pub const FRAME_UBO_BINDING: u32 = 0;
+pub const LIGHTS_UBO_BINDING: u32 = 1;
+pub const MATERIAL_UBO_BINDING: u32 = 2;
+
+pub const FRAME_BLOCK: &str = "Frame";
+pub const LIGHTS_BLOCK: &str = "Lights";
+pub const MATERIAL_BLOCK: &str = "Material";
+
+pub fn default_uniform_block_binding(block_name: &str) -> Option<u32> {
+    match block_name {
+        FRAME_BLOCK => Some(FRAME_UBO_BINDING),
+        LIGHTS_BLOCK => Some(LIGHTS_UBO_BINDING),
+        MATERIAL_BLOCK => Some(MATERIAL_UBO_BINDING),
+        _ => None,
+    }
+}
+
+impl ShaderProgram {
+    // Connects `uniform <block_name> { ... }` to a uniform buffer binding point, so a buffer
+    // attached there with `Buffer::bind_base` feeds the block. The same buffer can be shared by
+    // every program that binds the block to the same point.
+    pub unsafe fn bind_uniform_block(
+        &self,
+        block_name: &str,
+        binding: u32,
+    ) -> Result<(), ShaderError> {
+        debug::assert_context_current();
+        let name = CString::new(block_name)?;
+        let index = gl::GetUniformBlockIndex(self.id, name.as_ptr());
+
+        if index == gl::INVALID_INDEX {
+            return Err(ShaderError::UniformBlockNotFound(block_name.to_string()));
+        }
+
+        gl_trace!(
+            "glUniformBlockBinding({}, {} \"{}\", {})",
+            self.id,
+            index,
+            block_name,
+            binding
+        );
+        gl::UniformBlockBinding(self.id, index, binding);
+        Ok(())
+    }
+
+    // `bind_uniform_block` to the block's conventional binding point, which is returned.
+    pub unsafe fn bind_default_uniform_block(&self, block_name: &str) -> Result<u32, ShaderError> {
+        let binding = default_uniform_block_binding(block_name)
+            .ok_or_else(|| ShaderError::NoDefaultBinding(block_name.to_string()))?;
+        self.bind_uniform_block(block_name, binding)?;
+
+        Ok(binding)
+    }
+}
+
+impl ShaderProgram {
+    // Wraps a program object created elsewhere, which is then deleted on drop unless handed back
+    // with `into_raw`.
+    pub unsafe fn from_raw(id: u32) -> Self {
+        Self {
+            id,
+            locations: RefCell::new(HashMap::new()),
+            sources: RefCell::new(Vec::new()),
+            _not_send: PhantomData,
+        }
+    }
+
+    // Releases ownership of the GL program object without deleting it.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        // Free the location cache and source list, forgetting `self` would leak them.
+        self.locations.take();
+        self.sources.take();
+        std::mem::forget(self);
+        id
+    }
+}
+
 impl Drop for ShaderProgram {
     fn drop(&mut self) {
+        // Without a context there is nothing left to delete, the program went with it.
+        if !debug::context_is_current() {
+            return;
+        }
+
+        gl_trace!("glDeleteProgram({})", self.id);
         unsafe {
-            gl::DeleteShader(self.id);
+            gl::DeleteProgram(self.id);
+
+            // A program that is still in use is only flagged for deletion, so it can only be
+            // checked when it isn't current.
+            if cfg!(debug_assertions)
+                && get_integer(gl::CURRENT_PROGRAM) as u32 != self.id
+                && gl::IsProgram(self.id) == gl::TRUE
+            {
+                eprintln!(
+                    "Shader program {} is still alive after being dropped",
+                    self.id
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::{GlSpy, PROGRAM_BINARY, PROGRAM_BINARY_FORMAT};
+    use crate::render_state::RenderState;
+    use std::time::Duration;
+
+    // A fresh directory for the test's shader files.
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("opengl_rust_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let directory = scratch_directory("include_cycle");
+        fs::write(directory.join("a.glsl"), "#include \"b.glsl\"\n").unwrap();
+        fs::write(directory.join("b.glsl"), "#include \"a.glsl\"\n").unwrap();
+
+        match read_source(&directory.join("a.glsl")) {
+            Err(ShaderError::Include(message)) => assert!(message.contains("includes itself")),
+            result => panic!("expected an include error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn polls_included_files() {
+        let directory = scratch_directory("include_poll");
+        let main_path = directory.join("main.vert");
+        fs::write(&main_path, "#include \"common.glsl\"\nvoid main() {}\n").unwrap();
+        fs::write(directory.join("common.glsl"), "#include \"inner.glsl\"\n").unwrap();
+        fs::write(directory.join("inner.glsl"), "float inner;\n").unwrap();
+
+        let mut includes = Vec::new();
+        let source = read_source_with_includes(&main_path, &mut includes).unwrap();
+        assert!(source.starts_with("float inner;\n"));
+        let included: Vec<_> = includes.iter().map(|include| &include.path).collect();
+        assert_eq!(
+            included,
+            [
+                &directory.join("common.glsl").canonicalize().unwrap(),
+                &directory.join("inner.glsl").canonicalize().unwrap(),
+            ]
+        );
+
+        // Never deleted, there is no context.
+        let program = unsafe { ShaderProgram::from_raw(1) };
+        program.sources.borrow_mut().push(SourceFile {
+            modified: modified_time(&main_path),
+            path: main_path,
+            shader_type: gl::VERTEX_SHADER,
+            includes,
+        });
+        assert!(!program.poll_source_changes());
+
+        let inner = fs::File::options()
+            .write(true)
+            .open(directory.join("inner.glsl"))
+            .unwrap();
+        inner
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        assert!(program.poll_source_changes());
+        assert!(!program.poll_source_changes());
+    }
+
+    #[test]
+    fn clearing_the_uniform_cache_requeries_locations() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            let program = ShaderProgram::from_raw(7);
+            render_state.use_program(program.id);
+
+            program.set_uniform_f32("uTime", 1.0).unwrap();
+            program.set_uniform_f32("uTime", 2.0).unwrap();
+            assert_eq!(spy.count("glGetUniformLocation"), 1);
+
+            // What a relink of the same program object needs.
+            program.clear_uniform_cache();
+            program.set_uniform_f32("uTime", 3.0).unwrap();
+            assert_eq!(spy.count("glGetUniformLocation"), 2);
+            assert_eq!(spy.count("glUniform1f"), 3);
+        }
+    }
+
+    // Names are only turned into a CString on a cache miss, and that CString is dropped right
+    // after the lookup, so setting a uniform in a loop allocates nothing per call.
+    #[test]
+    fn setting_a_uniform_repeatedly_looks_it_up_once() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+
+        unsafe {
+            let program = ShaderProgram::from_raw(3);
+            render_state.use_program(program.id);
+
+            for i in 0..100_000 {
+                program.set_uniform_f32("xPosition", i as f32).unwrap();
+            }
+
+            assert_eq!(program.locations.borrow().len(), 1);
+        }
+
+        assert_eq!(spy.count("glGetUniformLocation"), 1);
+        assert_eq!(spy.count("glUniform1f"), 100_000);
+    }
+
+    #[test]
+    fn blocks_bind_to_their_conventional_points() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let program = ShaderProgram::from_raw(5);
+            assert_eq!(
+                program.bind_default_uniform_block(LIGHTS_BLOCK).unwrap(),
+                LIGHTS_UBO_BINDING
+            );
+            assert!(matches!(
+                program.bind_default_uniform_block("Shadows"),
+                Err(ShaderError::NoDefaultBinding(_))
+            ));
+        }
+
+        assert_eq!(
+            spy.calls(),
+            [
+                "glGetUniformBlockIndex(5, Lights)",
+                "glUniformBlockBinding(5, 0, 1)",
+                "glDeleteProgram(5)",
+            ]
+        );
+    }
+
+    #[test]
+    fn mat3_uniforms_upload_columns_in_order() {
+        let spy = GlSpy::new();
+        let mut render_state = RenderState::new();
+        let columns = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+
+        unsafe {
+            let program = ShaderProgram::from_raw(4);
+            render_state.use_program(program.id);
+            program.set_uniform_mat3("uNormalMatrix", &columns).unwrap();
+        }
+
+        assert!(spy.calls().contains(
+            &"glUniformMatrix3fv(0, 1, 0, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0])"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn sources_with_a_nul_byte_are_an_error() {
+        let spy = GlSpy::new();
+
+        let result = unsafe { Shader::new("void main() {}\0", gl::VERTEX_SHADER) };
+
+        assert!(matches!(result, Err(ShaderError::NulError(_))));
+        assert_eq!(spy.count("glCreateShader"), 0);
+    }
+
+    // Builds a program from a trivial vertex and fragment shader in `directory`, caching its
+    // binary in `directory/cache`.
+    unsafe fn build_cached(directory: &Path) -> ShaderProgram {
+        fs::write(directory.join("main.vert"), "void main() {}\n").unwrap();
+        fs::write(directory.join("main.frag"), "void main() {}\n").unwrap();
+        ShaderProgram::builder()
+            .vertex(directory.join("main.vert"))
+            .fragment(directory.join("main.frag"))
+            .with_binary_cache(directory.join("cache"))
+            .build()
+            .unwrap()
+    }
+
+    fn cache_files(directory: &Path) -> Vec<PathBuf> {
+        fs::read_dir(directory.join("cache"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn a_cached_binary_skips_compilation() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::NUM_PROGRAM_BINARY_FORMATS, 1);
+        let directory = scratch_directory("binary_cache_hit");
+
+        unsafe {
+            build_cached(&directory).into_raw();
+            assert_eq!(spy.count("glCompileShader"), 2);
+            assert_eq!(spy.count("glProgramBinary"), 0);
+
+            let mut expected = PROGRAM_BINARY_FORMAT.to_le_bytes().to_vec();
+            expected.extend_from_slice(PROGRAM_BINARY);
+            let files = cache_files(&directory);
+            assert_eq!(files.len(), 1);
+            assert_eq!(fs::read(&files[0]).unwrap(), expected);
+
+            let before = spy.calls().len();
+            build_cached(&directory).into_raw();
+            let calls = &spy.calls()[before..];
+
+            let loads: Vec<_> = calls
+                .iter()
+                .filter(|call| call.starts_with("glProgramBinary"))
+                .collect();
+            assert_eq!(loads.len(), 1);
+            assert!(loads[0].ends_with(&format!(
+                "{:#x}, {})",
+                PROGRAM_BINARY_FORMAT,
+                PROGRAM_BINARY.len()
+            )));
+            assert!(!calls.iter().any(|call| call.starts_with("glCompileShader")));
+            assert!(!calls.iter().any(|call| call.starts_with("glLinkProgram")));
+        }
+    }
+
+    #[test]
+    fn a_rejected_binary_falls_back_to_the_sources() {
+        let spy = GlSpy::new();
+        spy.set_integer(gl::NUM_PROGRAM_BINARY_FORMATS, 1);
+        let directory = scratch_directory("binary_cache_rejected");
+
+        unsafe {
+            build_cached(&directory).into_raw();
+
+            // What a binary from before a driver update looks like to the driver.
+            let cache_file = cache_files(&directory).remove(0);
+            let mut stale = PROGRAM_BINARY_FORMAT.to_le_bytes().to_vec();
+            stale.extend_from_slice(b"stale binary");
+            fs::write(&cache_file, stale).unwrap();
+
+            let before = spy.calls().len();
+            build_cached(&directory).into_raw();
+            let calls = &spy.calls()[before..];
+
+            let count = |function: &str| {
+                calls
+                    .iter()
+                    .filter(|call| call.starts_with(&format!("{}(", function)))
+                    .count()
+            };
+            assert_eq!(count("glProgramBinary"), 1);
+            assert_eq!(count("glCompileShader"), 2);
+            assert_eq!(count("glLinkProgram"), 1);
+
+            // Rebuilt from source, so the cache holds the working binary again.
+            assert!(fs::read(&cache_file).unwrap().ends_with(PROGRAM_BINARY));
+        }
+    }
+
+    #[test]
+    fn raw_ids_round_trip_without_deleting() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let shader = Shader::new("void main() {}", gl::VERTEX_SHADER).unwrap();
+            let program = ShaderProgram::new(std::slice::from_ref(&shader)).unwrap();
+
+            let id = shader.into_raw();
+            assert_eq!(Shader::from_raw(id).into_raw(), id);
+
+            let id = program.into_raw();
+            assert_eq!(ShaderProgram::from_raw(id).into_raw(), id);
+        }
+
+        assert!(!spy.calls().iter().any(|call| call.starts_with("glDelete")));
+    }
+
+    #[test]
+    fn binary_info_reports_the_linked_binary() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let shader = Shader::new("void main() {}", gl::VERTEX_SHADER).unwrap();
+            let program = ShaderProgram::new(&[shader]).unwrap();
+            assert_eq!(program.binary_info(), (0, 0));
+
+            spy.set_integer(gl::NUM_PROGRAM_BINARY_FORMATS, 1);
+            assert_eq!(
+                program.binary_info(),
+                (PROGRAM_BINARY_FORMAT, PROGRAM_BINARY.len())
+            );
         }
     }
 }
@@ -0,0 +1,161 @@
+// Packs values into a byte buffer following the std140 layout rules, ready to upload into a
+// uniform buffer whose block is declared with `layout(std140)`:
+//
+//   float        aligned to 4,  4 bytes
+//   vec2         aligned to 8,  8 bytes
+//   vec3         aligned to 16, 12 bytes (the next float may pack into the last 4 bytes)
+//   vec4         aligned to 16, 16 bytes
+//   mat4         four vec4 columns, aligned to 16, 64 bytes
+//   array of T   every element aligned to 16 and padded out to a multiple of 16
+//
+// Each push returns the byte offset the value was written at.
+#[derive(Debug, Clone, Default)]
+pub struct Std140Builder {
+    data: Vec<u8>,
+}
+
+impl Std140Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn align_to(&mut self, alignment: usize) {
+        let padding = (alignment - self.data.len() % alignment) % alignment;
+        self.data.resize(self.data.len() + padding, 0);
+    }
+
+    fn push_floats(&mut self, alignment: usize, values: &[f32]) -> usize {
+        self.align_to(alignment);
+        let offset = self.data.len();
+
+        for value in values {
+            self.data.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        offset
+    }
+
+    pub fn push_f32(&mut self, value: f32) -> usize {
+        self.push_floats(4, &[value])
+    }
+
+    pub fn push_vec2(&mut self, value: [f32; 2]) -> usize {
+        self.push_floats(8, &value)
+    }
+
+    pub fn push_vec3(&mut self, value: [f32; 3]) -> usize {
+        self.push_floats(16, &value)
+    }
+
+    pub fn push_vec4(&mut self, value: [f32; 4]) -> usize {
+        self.push_floats(16, &value)
+    }
+
+    // Column-major, matching what GLSL expects for a mat4.
+    pub fn push_mat4(&mut self, value: [[f32; 4]; 4]) -> usize {
+        let offset = self.push_vec4(value[0]);
+
+        for column in &value[1..] {
+            self.push_vec4(*column);
+        }
+
+        offset
+    }
+
+    pub fn push_f32_array(&mut self, values: &[f32]) -> usize {
+        self.align_to(16);
+        let offset = self.data.len();
+
+        for value in values {
+            self.push_floats(16, &[*value]);
+            self.align_to(16);
+        }
+
+        offset
+    }
+
+    pub fn push_vec4_array(&mut self, values: &[[f32; 4]]) -> usize {
+        self.align_to(16);
+        let offset = self.data.len();
+
+        for value in values {
+            self.push_vec4(*value);
+        }
+
+        offset
+    }
+
+    // Pads the end to a multiple of 16 so the result can also be used as an array element.
+    pub fn build(mut self) -> Vec<u8> {
+        self.align_to(16);
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_at(data: &[u8], offset: usize) -> f32 {
+        f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    // layout(std140) uniform Block {
+    //     float a;
+    //     vec3 b;
+    //     float c;
+    //     vec2 d;
+    //     mat4 e;
+    //     float f[2];
+    //     vec4 g;
+    // };
+    #[test]
+    fn offsets_match_std140() {
+        let mut builder = Std140Builder::new();
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        assert_eq!(builder.push_f32(1.0), 0);
+        assert_eq!(builder.push_vec3([2.0, 3.0, 4.0]), 16);
+        // Packs into the last 4 bytes of the vec3.
+        assert_eq!(builder.push_f32(5.0), 28);
+        assert_eq!(builder.push_vec2([6.0, 7.0]), 32);
+        assert_eq!(builder.push_mat4(identity), 48);
+        assert_eq!(builder.push_f32_array(&[8.0, 9.0]), 112);
+        assert_eq!(builder.push_vec4([10.0, 11.0, 12.0, 13.0]), 144);
+
+        let data = builder.build();
+        assert_eq!(data.len(), 160);
+
+        assert_eq!(f32_at(&data, 0), 1.0);
+        assert_eq!(f32_at(&data, 24), 4.0);
+        assert_eq!(f32_at(&data, 28), 5.0);
+        assert_eq!(f32_at(&data, 36), 7.0);
+        assert_eq!(f32_at(&data, 48 + 16 + 4), 1.0);
+        // Array elements have a stride of 16.
+        assert_eq!(f32_at(&data, 112), 8.0);
+        assert_eq!(f32_at(&data, 128), 9.0);
+        assert_eq!(f32_at(&data, 156), 13.0);
+    }
+
+    #[test]
+    fn build_pads_to_16() {
+        let mut builder = Std140Builder::new();
+        builder.push_f32(1.0);
+        assert_eq!(builder.len(), 4);
+        assert_eq!(builder.build().len(), 16);
+        assert!(Std140Builder::new().build().is_empty());
+    }
+}
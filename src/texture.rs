@@ -0,0 +1,391 @@
+use std::{marker::PhantomData, os::raw::c_void, path::Path};
+
+use gl::types::*;
+use thiserror::Error;
+
+use crate::debug;
+use crate::render_state::RenderState;
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("Error while loading image: {0}")]
+    ImageError(#[from] image::ImageError),
+}
+
+const CHECKERBOARD_SIZE: u32 = 64;
+const CHECKERBOARD_CELL: u32 = 8;
+
+fn checkerboard_pixels() -> Vec<u8> {
+    (0..CHECKERBOARD_SIZE * CHECKERBOARD_SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % CHECKERBOARD_SIZE, i / CHECKERBOARD_SIZE);
+            let (cell_x, cell_y) = (x / CHECKERBOARD_CELL, y / CHECKERBOARD_CELL);
+            if (cell_x ^ cell_y) & 1 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect()
+}
+
+// Lattice spacing of `noise_pixels`, in pixels.
+const NOISE_CELL: u32 = 8;
+
+// A pseudo-random value in 0..1 for the lattice point (x, y), always the same for the same seed.
+fn lattice_value(x: u32, y: u32, seed: u32) -> f32 {
+    let mut hash =
+        x.wrapping_mul(0x27d4_eb2d) ^ y.wrapping_mul(0x1656_67b1) ^ seed.wrapping_mul(0x9e37_79b9);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a_2d39);
+    hash ^= hash >> 15;
+
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+// Gray value noise: random values on a lattice NOISE_CELL pixels apart, smoothly interpolated in
+// between. Opaque RGBA8, determined entirely by the size and seed.
+fn noise_pixels(width: u32, height: u32, seed: u32) -> Vec<u8> {
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let (cell_x, cell_y) = (x / NOISE_CELL, y / NOISE_CELL);
+            let tx = smooth((x % NOISE_CELL) as f32 / NOISE_CELL as f32);
+            let ty = smooth((y % NOISE_CELL) as f32 / NOISE_CELL as f32);
+
+            let bottom = lerp(
+                lattice_value(cell_x, cell_y, seed),
+                lattice_value(cell_x + 1, cell_y, seed),
+                tx,
+            );
+            let top = lerp(
+                lattice_value(cell_x, cell_y + 1, seed),
+                lattice_value(cell_x + 1, cell_y + 1, seed),
+                tx,
+            );
+            let value = (lerp(bottom, top, ty) * 255.0).round() as u8;
+            [value, value, value, 255]
+        })
+        .collect()
+}
+
+// Levels in a full mipmap chain down to 1x1: floor(log2(max(width, height))) + 1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+// A 2D RGBA8 texture. Like the other GL wrappers it is tied to the context's thread.
+pub struct Texture {
+    id: u32,
+    width: u32,
+    height: u32,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Texture {
+    // Decodes a PNG or JPEG file and uploads it with a full mipmap chain.
+    pub unsafe fn from_file(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        // Images are stored top row first, GL expects the bottom row first.
+        let image = image::open(path)?.flipv().into_rgba8();
+
+        Ok(Self::from_rgba8(
+            image.width(),
+            image.height(),
+            image.as_raw(),
+            true,
+        ))
+    }
+
+    // Like `from_file`, but a file that can't be loaded gives a warning and the checkerboard
+    // instead of an error, so a missing texture is obvious on screen without stopping anything.
+    pub unsafe fn from_file_or_checkerboard(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        Self::from_file(path).unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to load texture {}, using the checkerboard: {}",
+                path.display(),
+                err
+            );
+            Self::checkerboard()
+        })
+    }
+
+    // Magenta and black squares, CHECKERBOARD_CELL pixels across, the usual stand-in for a
+    // missing texture.
+    pub unsafe fn checkerboard() -> Self {
+        Self::from_rgba8(
+            CHECKERBOARD_SIZE,
+            CHECKERBOARD_SIZE,
+            &checkerboard_pixels(),
+            true,
+        )
+    }
+
+    // Procedural gray noise, see `noise_pixels`. The same size and seed always give the same
+    // texture, for placeholder art and reproducible tests.
+    pub unsafe fn noise(width: u32, height: u32, seed: u32) -> Self {
+        Self::from_rgba8(width, height, &noise_pixels(width, height, seed), true)
+    }
+
+    // RGBA8 storage with undefined contents, to render into (see `Framebuffer::with_color_texture`).
+    // Clamped at the edges, so filtering near the border of a full-screen pass doesn't wrap the
+    // opposite border in.
+    pub unsafe fn empty(width: u32, height: u32) -> Self {
+        let texture = Self::generate(width, height, false);
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_S,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_T,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+
+        texture
+    }
+
+    // Uploads tightly packed RGBA8 pixels, bottom row first.
+    pub unsafe fn from_rgba8(width: u32, height: u32, pixels: &[u8], mipmaps: bool) -> Self {
+        debug_assert_eq!(pixels.len(), (width * height * 4) as usize);
+        let texture = Self::generate(width, height, mipmaps);
+
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_ptr() as *const c_void,
+        );
+
+        if mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        texture
+    }
+
+    // Same as `from_rgba8` with mipmaps, but allocates every level up front with glTexStorage2D.
+    // The size and format of immutable storage can't change afterwards, which spares the driver
+    // from checking the levels for consistency on every use. Needs GL 4.2.
+    pub unsafe fn from_rgba8_immutable(width: u32, height: u32, pixels: &[u8]) -> Self {
+        debug_assert_eq!(pixels.len(), (width * height * 4) as usize);
+        let texture = Self::generate(width, height, true);
+        let levels = mip_level_count(width, height);
+
+        gl_trace!("glTexStorage2D(GL_TEXTURE_2D, {} levels, GL_RGBA8)", levels);
+        gl::TexStorage2D(
+            gl::TEXTURE_2D,
+            levels as GLsizei,
+            gl::RGBA8,
+            width as GLsizei,
+            height as GLsizei,
+        );
+        gl::TexSubImage2D(
+            gl::TEXTURE_2D,
+            0,
+            0,
+            0,
+            width as GLsizei,
+            height as GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_ptr() as *const c_void,
+        );
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+
+        texture
+    }
+
+    // Creates and binds the texture object and sets its parameters, leaving the upload to the
+    // caller.
+    unsafe fn generate(width: u32, height: u32, mipmaps: bool) -> Self {
+        debug::assert_context_current();
+
+        let mut texture = Self {
+            id: 0,
+            width,
+            height,
+            _not_send: PhantomData,
+        };
+
+        gl::GenTextures(1, &mut texture.id);
+        gl_trace!(
+            "glGenTextures -> texture {}, {}x{} RGBA8",
+            texture.id,
+            width,
+            height
+        );
+        gl::BindTexture(gl::TEXTURE_2D, texture.id);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        let min_filter = if mipmaps {
+            gl::LINEAR_MIPMAP_LINEAR
+        } else {
+            gl::LINEAR
+        };
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+
+        // Rows of RGBA8 are always 4-byte aligned, but be explicit in case something changed it.
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        texture
+    }
+
+    // Wraps a texture created elsewhere, which is then deleted on drop unless handed back with
+    // `into_raw`.
+    pub unsafe fn from_raw(id: u32, width: u32, height: u32) -> Self {
+        Self {
+            id,
+            width,
+            height,
+            _not_send: PhantomData,
+        }
+    }
+
+    // Releases ownership of the GL texture without deleting it.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        std::mem::forget(self);
+
+        id
+    }
+}
+
+impl Texture {
+    // Skips the bind when the render state already has this texture on `unit`.
+    pub unsafe fn bind(&self, render_state: &mut RenderState, unit: u32) {
+        render_state.bind_texture(unit, self.id);
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        gl_trace!("glDeleteTextures({})", self.id);
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_spy::GlSpy;
+
+    #[test]
+    fn checkerboard_alternates_magenta_and_black() {
+        let pixels = checkerboard_pixels();
+        let pixel = |x: u32, y: u32| {
+            let offset = ((y * CHECKERBOARD_SIZE + x) * 4) as usize;
+            &pixels[offset..offset + 4]
+        };
+
+        assert_eq!(pixel(0, 0), [255, 0, 255, 255]);
+        assert_eq!(pixel(CHECKERBOARD_CELL, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(0, CHECKERBOARD_CELL), [0, 0, 0, 255]);
+        assert_eq!(
+            pixel(CHECKERBOARD_CELL, CHECKERBOARD_CELL),
+            [255, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    fn noise_is_determined_by_the_seed() {
+        let pixels = noise_pixels(33, 17, 7);
+
+        assert_eq!(pixels.len(), 33 * 17 * 4);
+        assert_eq!(pixels, noise_pixels(33, 17, 7));
+        assert_ne!(pixels, noise_pixels(33, 17, 8));
+        assert!(pixels.chunks(4).all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn missing_files_load_the_checkerboard() {
+        let spy = GlSpy::new();
+
+        let texture = unsafe { Texture::from_file_or_checkerboard("textures/missing.png") };
+
+        assert_eq!(texture.width(), CHECKERBOARD_SIZE);
+        assert_eq!(texture.height(), CHECKERBOARD_SIZE);
+        assert!(spy
+            .calls()
+            .contains(&format!("glTexImage2D({:#x}, 0, 64, 64)", gl::TEXTURE_2D)));
+    }
+
+    #[test]
+    fn counts_mip_levels_down_to_one_pixel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 20), 9);
+        assert_eq!(mip_level_count(20, 513), 10);
+    }
+
+    #[test]
+    fn immutable_textures_allocate_every_level() {
+        let spy = GlSpy::new();
+
+        let texture = unsafe { Texture::from_rgba8_immutable(16, 8, &[0; 16 * 8 * 4]) };
+
+        assert_eq!(texture.width(), 16);
+        let calls = spy.calls();
+        assert!(calls.contains(&format!(
+            "glTexStorage2D({:#x}, 5, {:#x}, 16, 8)",
+            gl::TEXTURE_2D,
+            gl::RGBA8
+        )));
+        assert!(calls.contains(&format!("glTexSubImage2D({:#x}, 0, 16, 8)", gl::TEXTURE_2D)));
+        assert_eq!(spy.count("glTexImage2D"), 0);
+    }
+
+    #[test]
+    fn raw_ids_round_trip_without_deleting() {
+        let spy = GlSpy::new();
+
+        unsafe {
+            let id = Texture::empty(4, 2).into_raw();
+
+            let texture = Texture::from_raw(id, 4, 2);
+            assert_eq!((texture.width(), texture.height()), (4, 2));
+            assert_eq!(texture.into_raw(), id);
+        }
+
+        assert!(!spy.calls().iter().any(|call| call.starts_with("glDelete")));
+    }
+}
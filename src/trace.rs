@@ -0,0 +1,104 @@
+// Records what the wrappers are doing to GL (uploads, binds, draws, state changes) as `log`
+// trace messages under the "gl" target. Only active with `--features gl-trace`; otherwise the
+// arguments are still type-checked but nothing is formatted or logged.
+#[cfg(feature = "gl-trace")]
+macro_rules! gl_trace {
+    ($($arg:tt)*) => {
+        log::trace!(target: "gl", $($arg)*)
+    };
+}
+
+#[cfg(not(feature = "gl-trace"))]
+macro_rules! gl_trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(all(test, feature = "gl-trace"))]
+mod tests {
+    use std::{
+        sync::Mutex,
+        thread::{self, ThreadId},
+    };
+
+    use crate::buffers::{Buffer, BufferUsage, VertexArray};
+    use crate::gl_spy::GlSpy;
+    use crate::render_state::RenderState;
+    use crate::renderer::{self, DrawMode};
+
+    // Keeps every "gl" message with the thread that logged it, since tests run in parallel.
+    struct CaptureLogger {
+        lines: Mutex<Vec<(ThreadId, String)>>,
+    }
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "gl"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let line = (thread::current().id(), record.args().to_string());
+                self.lines.lock().unwrap().push(line);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+
+    fn captured_lines() -> Vec<String> {
+        let current = thread::current().id();
+        LOGGER
+            .lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == current)
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    #[test]
+    fn traces_a_simple_draw() {
+        let _spy = GlSpy::new();
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        unsafe {
+            let vertex_array = VertexArray::new().unwrap();
+            let vertices = Buffer::<[f32; 2]>::new(gl::ARRAY_BUFFER).unwrap();
+            vertices.set_data(
+                &[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+                BufferUsage::StaticDraw,
+            );
+            renderer::draw_instanced(
+                &mut RenderState::new(),
+                &vertex_array,
+                DrawMode::Triangles,
+                3,
+                2,
+            );
+        }
+
+        assert_eq!(
+            captured_lines(),
+            [
+                "glGenVertexArrays -> vertex array 1",
+                "glGenBuffers -> buffer 2",
+                "glBindBuffer(0x8892, 2)",
+                "glBufferData(0x8892, 24 bytes, usage 0x88e4) on buffer 2",
+                "glBindVertexArray(1)",
+                "glDrawArraysInstanced(Triangles, 0, 3, 2 instances)",
+                "glDeleteBuffers(2)",
+                "glDeleteVertexArrays(1)",
+            ]
+        );
+    }
+}